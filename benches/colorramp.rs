@@ -0,0 +1,77 @@
+//! Throughput of `colorramp::fill` for common ramp sizes
+//!
+//! Run with `cargo bench`. The settings cover the neutral temperature,
+//! common night ones and the warm end of the table. Every iteration
+//! applies the same setting, so the white point comes from the cache of
+//! the options as it does in continuous mode.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use redshift_minimal_rs::colorramp::{self, FillOptions};
+use redshift_minimal_rs::ColorSetting;
+
+const RAMP_SIZES: [usize; 3] = [256, 1024, 4096];
+
+fn identity_ramp(size: usize) -> Vec<u16> {
+    let u16_max1 = u16::MAX as f64 + 1.0;
+    (0..size)
+        .map(|i| ((i as f64 / size as f64) * u16_max1) as u16)
+        .collect()
+}
+
+fn settings() -> Vec<(&'static str, ColorSetting)> {
+    vec![
+        ("neutral", ColorSetting::neutral()),
+        ("night", ColorSetting::neutral().with_temp(3400)),
+        (
+            "dimmed",
+            ColorSetting::neutral()
+                .with_temp(4500)
+                .with_brightness(0.7)
+                .with_gamma([0.9, 0.9, 1.0]),
+        ),
+        ("candle", ColorSetting::neutral().with_temp(1000)),
+    ]
+}
+
+fn fill(c: &mut Criterion) {
+    for dither in [false, true] {
+        let options = FillOptions {
+            dither,
+            ..FillOptions::default()
+        };
+        let mut group = c.benchmark_group(if dither { "fill_dithered" } else { "fill" });
+
+        for size in RAMP_SIZES {
+            let identity = identity_ramp(size);
+            group.throughput(Throughput::Elements(3 * size as u64));
+
+            for (name, setting) in settings() {
+                group.bench_with_input(
+                    BenchmarkId::new(name, size),
+                    &setting,
+                    |bencher, setting| {
+                        let (mut r, mut g, mut b) =
+                            (identity.clone(), identity.clone(), identity.clone());
+                        bencher.iter(|| {
+                            r.copy_from_slice(&identity);
+                            g.copy_from_slice(&identity);
+                            b.copy_from_slice(&identity);
+                            colorramp::fill(
+                                &mut r,
+                                &mut g,
+                                &mut b,
+                                black_box(setting),
+                                size,
+                                &options,
+                            );
+                        })
+                    },
+                );
+            }
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, fill);
+criterion_main!(benches);
@@ -0,0 +1,110 @@
+use super::Result;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the kernel lists the backlight devices
+pub const SYSFS_BACKLIGHT: &str = "/sys/class/backlight";
+
+/// A backlight device of the sysfs backlight class
+///
+/// Unlike the brightness of a color setting, which only scales the gamma
+/// ramps, this changes how bright the panel actually is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backlight {
+    /// The directory of the device (eg `/sys/class/backlight/intel_backlight`)
+    pub path: PathBuf,
+
+    /// The raw value of full brightness
+    pub max_brightness: u32,
+}
+
+impl Backlight {
+    /// Find the first backlight device, by name
+    pub fn find() -> Result<Backlight> {
+        Backlight::find_in(Path::new(SYSFS_BACKLIGHT))
+    }
+
+    /// Find the first backlight device, by name, in the given directory
+    pub fn find_in(dir: &Path) -> Result<Backlight> {
+        let mut paths = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .collect::<Vec<_>>(),
+            Err(_) => vec![],
+        };
+        paths.sort_unstable();
+
+        for path in paths {
+            if let Ok(max) = read_value(&path.join("max_brightness")) {
+                if max > 0 {
+                    return Ok(Backlight {
+                        path,
+                        max_brightness: max,
+                    });
+                }
+            }
+        }
+        Err(format!("No backlight device found in {}", dir.display()).into())
+    }
+
+    /// The current brightness, between 0.0 and 1.0
+    pub fn level(&self) -> Result<f64> {
+        let raw = read_value(&self.path.join("brightness"))?;
+        Ok(raw as f64 / self.max_brightness as f64)
+    }
+
+    /// Set the brightness, given between 0.0 and 1.0
+    pub fn set_level(&self, level: f64) -> Result<()> {
+        let raw = (level.clamp(0.0, 1.0) * self.max_brightness as f64).round() as u32;
+        let path = self.path.join("brightness");
+        fs::write(&path, format!("{raw}\n"))
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()).into())
+    }
+}
+
+fn read_value(path: &Path) -> Result<u32> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    content
+        .trim()
+        .parse::<u32>()
+        .map_err(|_| format!("Invalid value in {} (was '{content}')", path.display()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_device_is_an_error() {
+        assert!(Backlight::find_in(Path::new("/nonexistent/backlight")).is_err());
+    }
+
+    #[test]
+    fn level_is_scaled_to_the_device() {
+        let dir = std::env::temp_dir().join(format!(
+            "redshift-minimal-rs-backlight-{}",
+            std::process::id()
+        ));
+        let device = dir.join("acpi_video0");
+        fs::create_dir_all(&device).unwrap();
+        fs::write(device.join("max_brightness"), "937\n").unwrap();
+        fs::write(device.join("brightness"), "937\n").unwrap();
+
+        let backlight = Backlight::find_in(&dir).unwrap();
+        assert_eq!(backlight.path, device);
+        assert_eq!(backlight.max_brightness, 937);
+        assert_eq!(backlight.level().unwrap(), 1.0);
+
+        backlight.set_level(0.5).unwrap();
+        assert_eq!(
+            fs::read_to_string(device.join("brightness")).unwrap(),
+            "469\n"
+        );
+        backlight.set_level(2.0).unwrap();
+        assert_eq!(backlight.level().unwrap(), 1.0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
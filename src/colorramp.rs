@@ -1,4 +1,190 @@
 use crate::transition::ColorSetting;
+use crate::Result;
+use crate::MIN_BRIGHTNESS;
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+/// Exponent relating perceived lightness to the ramp values, for
+/// perceptual brightness
+const PERCEPTUAL_GAMMA: f64 = 2.2;
+
+/// Quantization step the dithering spreads the error over, the size of
+/// one step of an 8 bit ramp
+const DITHER_STEP: f64 = 256.0;
+
+/// Thresholds of the ordered dithering pattern, in quantization steps
+const DITHER_PATTERN: [f64; 4] = [0.0, 0.5, 0.25, 0.75];
+
+/// How `fill` computes the ramps, beyond what the color setting says
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FillOptions {
+    /// Dither the ramps, to reduce banding on low bit depth panels
+    pub dither: bool,
+
+    /// Take the brightness as a perceived lightness rather than a linear
+    /// factor
+    ///
+    /// Lightness is taken as the ramp value to the power of 1/2.2, so the
+    /// ramps are scaled by the brightness to the power of 2.2: a brightness
+    /// of 0.5 scales them by about 0.22 instead of 0.5.
+    pub perceptual_brightness: bool,
+
+    /// Invert the colors
+    ///
+    /// The inversion comes after the temperature and brightness, so a warm
+    /// setting gives a warm inverted screen rather than an inverted warm one.
+    pub invert: bool,
+
+    /// Look the white points of the temperatures up in this table instead
+    /// of the built-in one
+    pub white_point_table: Option<Arc<WhitePointTable>>,
+
+    /// The white point last looked up, see `WhitePointCache`
+    pub white_point_cache: WhitePointCache,
+}
+
+impl FillOptions {
+    /// The white point a color setting applies with these options: its
+    /// override if it has one, otherwise the white point of its
+    /// temperature in the table in use
+    pub fn white_point_of(&self, setting: &ColorSetting) -> [f64; 3] {
+        if let Some(white_point) = setting.white_point {
+            return white_point;
+        }
+        self.white_point_cache
+            .get(setting.temp, &self.white_point_table, || {
+                match self.white_point_table {
+                    Some(ref table) => table.white_point(setting.temp),
+                    None => white_point(setting.temp),
+                }
+            })
+    }
+}
+
+/// The white point of the temperature looked up last, and the table it
+/// was looked up in
+///
+/// Continuous mode applies the same temperature over and over during
+/// the day and the night, which then skips the table interpolation.
+/// Every copy of the options has its own cache. As it never changes the
+/// result, all caches compare equal.
+#[derive(Debug, Clone, Default)]
+pub struct WhitePointCache(RefCell<Option<CachedWhitePoint>>);
+
+type CachedWhitePoint = (i32, Option<Arc<WhitePointTable>>, [f64; 3]);
+
+impl WhitePointCache {
+    fn get(
+        &self,
+        temp: i32,
+        table: &Option<Arc<WhitePointTable>>,
+        compute: impl FnOnce() -> [f64; 3],
+    ) -> [f64; 3] {
+        let mut cached = self.0.borrow_mut();
+        match *cached {
+            Some((t, ref cached_table, white_point))
+                if t == temp && same_table(cached_table, table) =>
+            {
+                white_point
+            }
+            _ => {
+                let white_point = compute();
+                *cached = Some((temp, table.clone(), white_point));
+                white_point
+            }
+        }
+    }
+}
+
+impl PartialEq for WhitePointCache {
+    fn eq(&self, _: &WhitePointCache) -> bool {
+        true
+    }
+}
+
+fn same_table(a: &Option<Arc<WhitePointTable>>, b: &Option<Arc<WhitePointTable>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// A table of blackbody white points, laid out like the built-in one
+///
+/// See `parse_white_point_table`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WhitePointTable(Vec<f64>);
+
+impl WhitePointTable {
+    /// Look up the white point of the temperature in the table, like
+    /// `white_point` does in the built-in one
+    pub fn white_point(&self, temp: i32) -> [f64; 3] {
+        white_point_in(&self.0, temp)
+    }
+}
+
+/// Number of bits a display takes per ramp entry, between 1 and 16
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RampDepth(u32);
+
+impl RampDepth {
+    /// The full 16 bits of the ramp entries
+    pub const FULL: RampDepth = RampDepth(16);
+
+    /// Make sure the depth is one ramps can be scaled to
+    pub fn new(bits: u32) -> Result<RampDepth> {
+        if !(1..=16).contains(&bits) {
+            return Err(format!("Ramp depth must be between 1 and 16 bits (was {bits})").into());
+        }
+        Ok(RampDepth(bits))
+    }
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+/// Set the ramps to the identity ramp of their length, which leaves
+/// the colors as they are
+pub fn fill_identity(gamma_r: &mut [u16], gamma_g: &mut [u16], gamma_b: &mut [u16]) {
+    let u16_max1 = u16::MAX as f64 + 1.0;
+    for ramp in [gamma_r, gamma_g, gamma_b] {
+        let size = ramp.len() as f64;
+        for (i, v) in ramp.iter_mut().enumerate() {
+            *v = ((i as f64 / size) * u16_max1) as u16;
+        }
+    }
+}
+
+/// Generate color ramps from the given color setting for a display that
+/// takes `depth` bits per ramp entry
+///
+/// Unlike `fill` this does not start from the current contents of the
+/// ramps, but from the identity ramp of their length. Every entry ends up
+/// between 0 and `2^depth - 1`.
+pub fn fill_scaled(
+    gamma_r: &mut [u16],
+    gamma_g: &mut [u16],
+    gamma_b: &mut [u16],
+    setting: &ColorSetting,
+    depth: RampDepth,
+    options: &FillOptions,
+) {
+    let size = gamma_r.len();
+    fill_identity(gamma_r, gamma_g, gamma_b);
+    fill(gamma_r, gamma_g, gamma_b, setting, size, options);
+
+    let shift = 16 - depth.bits();
+    for v in gamma_r
+        .iter_mut()
+        .chain(gamma_g.iter_mut())
+        .chain(gamma_b.iter_mut())
+    {
+        *v >>= shift;
+    }
+}
 
 /**
  * Generate color ramps from the given color setting and ramp size,
@@ -10,31 +196,201 @@ pub fn fill(
     gamma_b: &mut [u16],
     setting: &ColorSetting,
     size: usize,
+    options: &FillOptions,
 ) {
-    let alpha = (setting.temp as f64 % 100.0) / 100.0;
-    let temp_index = (((setting.temp - 1000) / 100) * 3) as usize;
+    let white_points = options.white_point_of(setting);
+    let brightness = setting.brightness_rgb.map(|factor| {
+        let brightness = safe_brightness(setting.brightness) * factor;
+        if options.perceptual_brightness {
+            brightness.powf(PERCEPTUAL_GAMMA)
+        } else {
+            brightness
+        }
+    });
 
-    let white_points = interpolate_color(
-        alpha,
-        &BLACKBODY_COLOR[temp_index..temp_index + 3],
-        &BLACKBODY_COLOR[temp_index + 3..temp_index + 6],
-    );
+    // Only ramps that start out monotonic can be expected to stay so
+    let monotonic_input = cfg!(debug_assertions)
+        && [&*gamma_r, &*gamma_g, &*gamma_b]
+            .iter()
+            .all(|ramp| is_monotonic(&ramp[..size]));
 
-    // Compute gamma, based on other gamma value
-    let u16_max1 = u16::max_value() as f64 + 1.0;
+    // Compute gamma, based on other gamma value. A brightness above 1
+    // can overshoot the ramp, which is clamped to its maximum.
+    let u16_max1 = u16::MAX as f64 + 1.0;
     let compute_gamma = |g: u16, c: usize| {
         let y = g as f64 / u16_max1;
-        let f = (y * setting.brightness * white_points[c]).powf(setting.gamma[c].recip());
-        (f * u16_max1) as u16
+        let f = (y * brightness[c] * white_points[c]).powf(setting.gamma[c].recip());
+        let v = f * u16_max1;
+        debug_assert!(
+            v >= 0.0,
+            "gamma ramp entry {v} is out of range for {setting:?}"
+        );
+        v.min(u16::MAX as f64)
     };
 
-    for i in 0..size {
-        gamma_r[i] = compute_gamma(gamma_r[i], 0);
-        gamma_g[i] = compute_gamma(gamma_g[i], 1);
-        gamma_b[i] = compute_gamma(gamma_b[i], 2);
+    if !options.dither {
+        for i in 0..size {
+            gamma_r[i] = compute_gamma(gamma_r[i], 0) as u16;
+            gamma_g[i] = compute_gamma(gamma_g[i], 1) as u16;
+            gamma_b[i] = compute_gamma(gamma_b[i], 2) as u16;
+        }
+    } else {
+        // Round every entry up or down to a quantization step following
+        // the pattern, so that the error averages out over adjacent
+        // entries. Entries never go below the previous one to keep the
+        // ramp monotonic.
+        for (c, ramp) in [&mut *gamma_r, &mut *gamma_g, &mut *gamma_b]
+            .into_iter()
+            .enumerate()
+        {
+            let mut previous = 0;
+            for (i, v) in ramp[..size].iter_mut().enumerate() {
+                let steps = compute_gamma(*v, c) / DITHER_STEP + DITHER_PATTERN[i % 4];
+                let dithered = (steps.floor() * DITHER_STEP).min(u16::MAX as f64) as u16;
+                *v = dithered.max(previous);
+                previous = *v;
+            }
+        }
+    }
+
+    if monotonic_input {
+        for (name, ramp) in [
+            ("red", &*gamma_r),
+            ("green", &*gamma_g),
+            ("blue", &*gamma_b),
+        ] {
+            debug_assert!(
+                is_monotonic(&ramp[..size]),
+                "{name} gamma ramp is not monotonic for {setting:?}"
+            );
+        }
+    }
+
+    if options.invert {
+        for ramp in [gamma_r, gamma_g, gamma_b] {
+            invert(&mut ramp[..size]);
+        }
+    }
+}
+
+/// Replace every entry of the ramp by its distance to the maximum
+fn invert(ramp: &mut [u16]) {
+    for v in ramp.iter_mut() {
+        *v = u16::MAX - *v;
     }
 }
 
+/// The brightness, raised to `MIN_BRIGHTNESS` when below it
+///
+/// Settings are validated when parsed, this is a last line of defense
+/// against a black screen the user cannot see to recover from.
+fn safe_brightness(brightness: f64) -> f64 {
+    // max() also turns NaN into the minimum
+    brightness.max(MIN_BRIGHTNESS)
+}
+
+/// Whether no entry of the ramp is lower than the one before
+fn is_monotonic(ramp: &[u16]) -> bool {
+    ramp.windows(2).all(|w| w[0] <= w[1])
+}
+
+/// The white point a color setting applies: its override if it has
+/// one, otherwise the white point of its temperature in the built-in
+/// table
+pub fn white_point_of(setting: &ColorSetting) -> [f64; 3] {
+    if let Some(white_point) = setting.white_point {
+        return white_point;
+    }
+    white_point(setting.temp)
+}
+
+/// Look up the white point of the temperature in the blackbody table
+///
+/// Returns the red, green and blue factors, each between 0 and 1, that
+/// a neutral (6500K) white is scaled by to look like the given
+/// temperature. The table covers 1000K to 25100K: temperatures outside
+/// of it are clamped to its first or last entry, anything in between is
+/// interpolated from the two nearest entries.
+pub fn white_point(temp: i32) -> [f64; 3] {
+    white_point_in(&BLACKBODY_COLOR, temp)
+}
+
+/// Look up the white point of the temperature in a table laid out like
+/// `BLACKBODY_COLOR`
+fn white_point_in(table: &[f64], temp: i32) -> [f64; 3] {
+    let temp = temp.clamp(BLACKBODY_MIN_TEMP, BLACKBODY_MAX_TEMP);
+    let alpha = ((temp - BLACKBODY_MIN_TEMP) % BLACKBODY_STEP) as f64 / BLACKBODY_STEP as f64;
+    let temp_index = ((temp - BLACKBODY_MIN_TEMP) / BLACKBODY_STEP * 3) as usize;
+
+    // The last entry has nothing to interpolate towards
+    if temp_index + 3 == table.len() {
+        return [
+            table[temp_index],
+            table[temp_index + 1],
+            table[temp_index + 2],
+        ];
+    }
+
+    interpolate_color(
+        alpha,
+        &table[temp_index..temp_index + 3],
+        &table[temp_index + 3..temp_index + 6],
+    )
+}
+
+/// Parse a white point table to use instead of the built-in one
+///
+/// Every line holds a temperature and its red, green and blue factors,
+/// separated by spaces or commas (eg `1000, 1.0, 0.18172716, 0.0`).
+/// Empty lines and anything after a `#` are ignored. Like the built-in
+/// table, it must cover 1000K to 25100K in steps of 100K, in order, with
+/// factors between 0 and 1.
+pub fn parse_white_point_table(content: &str) -> Result<WhitePointTable> {
+    let rows = ((BLACKBODY_MAX_TEMP - BLACKBODY_MIN_TEMP) / BLACKBODY_STEP + 1) as usize;
+    let mut table = Vec::with_capacity(3 * rows);
+
+    let lines = content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split('#').next().unwrap_or("").trim()))
+        .filter(|(_, line)| !line.is_empty());
+    for (row, (number, line)) in lines.enumerate() {
+        let fields = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|f| !f.is_empty())
+            .collect::<Vec<_>>();
+        let expected = BLACKBODY_MIN_TEMP + row as i32 * BLACKBODY_STEP;
+        match fields[..] {
+            [temp, r, g, b] if temp.parse::<i32>().ok() == Some(expected) => {
+                for factor in [r, g, b] {
+                    match factor.parse::<f64>() {
+                        Ok(f) if (0.0..=1.0).contains(&f) => table.push(f),
+                        _ => {
+                            return Err(format!(
+                                "Line {number}: factors must be between 0 and 1 (was '{factor}')"
+                            )
+                            .into())
+                        }
+                    }
+                }
+            }
+            [_, _, _, _] => {
+                return Err(format!("Line {number}: expected the entry of {expected}K").into())
+            }
+            _ => return Err(format!("Line {number}: expected TEMP R G B (was '{line}')").into()),
+        }
+    }
+
+    if table.len() != 3 * rows {
+        return Err(format!(
+            "Expected {rows} entries from {BLACKBODY_MIN_TEMP}K to {BLACKBODY_MAX_TEMP}K (got {})",
+            table.len() / 3
+        )
+        .into());
+    }
+    Ok(WhitePointTable(table))
+}
+
 fn interpolate_color<'a>(a: f64, c1: &'a [f64], c2: &'a [f64]) -> [f64; 3] {
     [
         (1.0 - a) * c1[0] + a * c2[0],
@@ -43,6 +399,13 @@ fn interpolate_color<'a>(a: f64, c1: &'a [f64], c2: &'a [f64]) -> [f64; 3] {
     ]
 }
 
+/// Temperature of the first entry of the blackbody table
+const BLACKBODY_MIN_TEMP: i32 = 1000;
+/// Temperature of the last entry of the blackbody table
+const BLACKBODY_MAX_TEMP: i32 = 25100;
+/// Temperature difference between two entries of the blackbody table
+const BLACKBODY_STEP: i32 = 100;
+
 /* Whitepoint values for temperatures at 100K intervals.
 These will be interpolated for the actual temperature.
 This table was provided by Ingo Thies, 2013. See
@@ -145,3 +508,406 @@ const BLACKBODY_COLOR: [f64; 726] = [
     0.62808356, 0.75331217, 1.00000000, 0.62774186, 0.75306977, 1.00000000, /* 25000K */
     0.62740336, 0.75282962, 1.00000000, /* 25100K */
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MAX_GAMMA, MIN_GAMMA};
+
+    /// The built-in table, in the format of a white point table file
+    fn builtin_table_file() -> String {
+        BLACKBODY_COLOR
+            .chunks(3)
+            .enumerate()
+            .map(|(i, c)| {
+                let temp = BLACKBODY_MIN_TEMP + i as i32 * BLACKBODY_STEP;
+                format!("{temp}, {}, {}, {}\n", c[0], c[1], c[2])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn white_point_table_round_trips() {
+        let content = format!("# TEMP R G B\n\n{}", builtin_table_file());
+        assert_eq!(
+            parse_white_point_table(&content).unwrap().0,
+            BLACKBODY_COLOR
+        );
+
+        let spaces = builtin_table_file().replace(", ", "  ");
+        assert_eq!(parse_white_point_table(&spaces).unwrap().0, BLACKBODY_COLOR);
+
+        // Lookups interpolate in the given table
+        let mut table = BLACKBODY_COLOR.to_vec();
+        table[3..6].copy_from_slice(&[1.0, 1.0, 1.0]);
+        assert_eq!(white_point_in(&table, 1100), [1.0, 1.0, 1.0]);
+        assert_eq!(white_point_in(&table, 1050)[0], 1.0);
+        assert_eq!(white_point_in(&BLACKBODY_COLOR, 4500), white_point(4500));
+
+        // Only the options given the table use it
+        let options = FillOptions {
+            white_point_table: Some(Arc::new(WhitePointTable(table))),
+            ..FillOptions::default()
+        };
+        let setting = ColorSetting::neutral().with_temp(1100);
+        assert_eq!(options.white_point_of(&setting), [1.0, 1.0, 1.0]);
+        assert_eq!(
+            FillOptions::default().white_point_of(&setting),
+            white_point(1100)
+        );
+    }
+
+    #[test]
+    fn repeated_temperatures_skip_the_interpolation() {
+        let options = FillOptions::default();
+        let setting = ColorSetting::neutral().with_temp(3000);
+        assert_eq!(options.white_point_of(&setting), white_point(3000));
+
+        // A cached value no table gives shows that the lookup was skipped
+        *options.white_point_cache.0.borrow_mut() = Some((3000, None, [0.5; 3]));
+        assert_eq!(options.white_point_of(&setting), [0.5; 3]);
+        assert_eq!(options.clone().white_point_of(&setting), [0.5; 3]);
+
+        // Another temperature or table is looked up again
+        let warmer = setting.with_temp(2000);
+        assert_eq!(options.white_point_of(&warmer), white_point(2000));
+        let options = FillOptions {
+            white_point_table: Some(Arc::new(WhitePointTable(BLACKBODY_COLOR.to_vec()))),
+            ..FillOptions::default()
+        };
+        *options.white_point_cache.0.borrow_mut() = Some((3000, None, [0.5; 3]));
+        assert_eq!(options.white_point_of(&setting), white_point(3000));
+    }
+
+    #[test]
+    fn bad_white_point_tables_are_rejected() {
+        let file = builtin_table_file();
+        let lines = file.lines().collect::<Vec<_>>();
+
+        let missing_row = lines[1..].join("\n");
+        let swapped = [&[lines[1], lines[0]], &lines[2..]].concat().join("\n");
+        let too_many = format!("{file}25200, 1.0, 1.0, 1.0\n");
+        let out_of_range = file.replacen("1000, 1, ", "1000, 1.5, ", 1);
+        let malformed = file
+            .replacen("1000, 1, ", "1000, 1 ", 1)
+            .replacen(", 0\n", "\n", 1);
+
+        for (name, content) in [
+            ("empty", ""),
+            ("missing row", &missing_row),
+            ("swapped rows", &swapped),
+            ("too many rows", &too_many),
+            ("out of range", &out_of_range),
+            ("malformed", &malformed),
+        ] {
+            assert_ne!(content, file, "{name}");
+            assert!(parse_white_point_table(content).is_err(), "{name}");
+        }
+    }
+
+    fn identity_ramp(size: usize) -> Vec<u16> {
+        let u16_max1 = u16::MAX as f64 + 1.0;
+        (0..size)
+            .map(|i| ((i as f64 / size as f64) * u16_max1) as u16)
+            .collect()
+    }
+
+    fn filled(
+        setting: &ColorSetting,
+        size: usize,
+        options: &FillOptions,
+    ) -> (Vec<u16>, Vec<u16>, Vec<u16>) {
+        let (mut r, mut g, mut b) = (
+            identity_ramp(size),
+            identity_ramp(size),
+            identity_ramp(size),
+        );
+        fill(&mut r, &mut g, &mut b, setting, size, options);
+        (r, g, b)
+    }
+
+    /// The ramps of a temperature with the default options
+    fn filled_temp(temp: i32, size: usize) -> (Vec<u16>, Vec<u16>, Vec<u16>) {
+        filled(
+            &ColorSetting::neutral().with_temp(temp),
+            size,
+            &FillOptions::default(),
+        )
+    }
+
+    #[test]
+    fn dithered_ramps_are_monotonic() {
+        let options = FillOptions {
+            dither: true,
+            ..Default::default()
+        };
+        for temp in [1000, 3000, 4500, 6500, 25000] {
+            for brightness in [0.3, 1.0] {
+                let setting = ColorSetting::neutral()
+                    .with_temp(temp)
+                    .with_brightness(brightness);
+                let (r, g, b) = filled(&setting, 1024, &options);
+                for ramp in [r, g, b] {
+                    assert!(ramp.windows(2).all(|w| w[0] <= w[1]));
+                    assert_eq!(ramp[0], 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn extreme_settings_keep_ramps_monotonic() {
+        for temp in [1000, 6500, 25000] {
+            for brightness in [0.1, 1.0, 2.0] {
+                for gamma in [MIN_GAMMA, 1.0, MAX_GAMMA] {
+                    for dither in [false, true] {
+                        let setting = ColorSetting::neutral()
+                            .with_temp(temp)
+                            .with_brightness(brightness)
+                            .with_gamma([gamma; 3]);
+                        let options = FillOptions {
+                            dither,
+                            ..Default::default()
+                        };
+                        let (r, g, b) = filled(&setting, 256, &options);
+                        for ramp in [r, g, b] {
+                            assert!(is_monotonic(&ramp));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn white_point_override_replaces_the_temperature() {
+        let setting = ColorSetting::neutral().with_white_point(Some([1.0, 0.5, 0.0]));
+        let (r, g, b) = filled(&setting, 256, &FillOptions::default());
+        assert_eq!(r, identity_ramp(256));
+        assert_eq!(g[128], 16384);
+        assert!(b.iter().all(|&v| v == 0));
+        assert_eq!(white_point_of(&setting.with_temp(3000)), [1.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn overshooting_brightness_is_clamped() {
+        let setting = ColorSetting::neutral().with_brightness(2.0);
+        let (r, _, _) = filled(&setting, 256, &FillOptions::default());
+        assert_eq!(r[255], u16::MAX);
+        assert_eq!(r[64], 32768);
+    }
+
+    #[test]
+    fn zero_brightness_is_clamped() {
+        let ramps = |brightness| {
+            let setting = ColorSetting::neutral().with_brightness(brightness);
+            filled(&setting, 256, &FillOptions::default()).0
+        };
+        let dimmest = ramps(MIN_BRIGHTNESS);
+        assert!(dimmest.iter().any(|&v| v > 0));
+        assert_eq!(ramps(0.0), dimmest);
+        assert_eq!(ramps(-1.0), dimmest);
+        assert_eq!(ramps(f64::NAN), dimmest);
+        assert_eq!(safe_brightness(0.5), 0.5);
+    }
+
+    #[test]
+    fn channels_are_scaled_by_their_brightness() {
+        let setting = ColorSetting::neutral()
+            .with_brightness(0.5)
+            .with_brightness_rgb([1.0, 0.5, 0.25]);
+        let (r, g, b) = filled(&setting, 256, &FillOptions::default());
+        assert_eq!((r[128], g[128], b[128]), (16384, 8192, 4096));
+
+        let equal = ColorSetting::neutral().with_brightness(0.5);
+        let (r2, g2, b2) = filled(&equal, 256, &FillOptions::default());
+        assert_eq!(r, r2);
+        assert_eq!(g2, r2);
+        assert_eq!(b2, r2);
+    }
+
+    #[test]
+    fn perceptual_brightness_is_darker_than_linear() {
+        let ramps = |setting: &ColorSetting, perceptual| {
+            let options = FillOptions {
+                perceptual_brightness: perceptual,
+                ..Default::default()
+            };
+            filled(setting, 256, &options).0
+        };
+        let setting = ColorSetting::neutral().with_brightness(0.5);
+        let (linear, perceptual) = (ramps(&setting, false), ramps(&setting, true));
+
+        assert_eq!(linear[128], 16384);
+        assert_eq!(perceptual[128], (32768.0 * 0.5f64.powf(2.2)) as u16);
+        assert!(linear
+            .iter()
+            .zip(perceptual.iter())
+            .skip(1)
+            .all(|(l, p)| p < l));
+        assert!(is_monotonic(&perceptual));
+
+        // Full brightness is the same either way
+        assert_eq!(ramps(&ColorSetting::neutral(), true), identity_ramp(256));
+    }
+
+    #[test]
+    fn inverting_twice_is_identity() {
+        let (original, _, _) = filled_temp(3000, 256);
+        let mut ramp = original.clone();
+        invert(&mut ramp);
+        assert_eq!(ramp[0], u16::MAX);
+        assert_eq!(ramp[255], u16::MAX - original[255]);
+        assert!(ramp.windows(2).all(|w| w[0] >= w[1]));
+        invert(&mut ramp);
+        assert_eq!(ramp, original);
+    }
+
+    #[test]
+    fn fill_inverts_after_temperature_and_brightness() {
+        let setting = ColorSetting::neutral().with_temp(3000).with_brightness(0.5);
+        let ramps = |invert| {
+            let options = FillOptions {
+                invert,
+                ..Default::default()
+            };
+            let (r, g, b) = filled(&setting, 256, &options);
+            [r, g, b]
+        };
+        let (plain, inverted) = (ramps(false), ramps(true));
+
+        for (plain, inverted) in plain.iter().zip(inverted.iter()) {
+            assert!(plain
+                .iter()
+                .zip(inverted.iter())
+                .all(|(&p, &i)| i == u16::MAX - p));
+        }
+
+        // Inverting the identity before adjusting it would leave black
+        // dimmed and warm instead of full white
+        assert!(inverted.iter().all(|ramp| ramp[0] == u16::MAX));
+        let [r, _, b] = &inverted;
+        assert!(b[255] > r[255]);
+    }
+
+    #[test]
+    fn dithered_ramps_stay_close() {
+        let options = FillOptions {
+            dither: true,
+            ..Default::default()
+        };
+        let (r, _, _) = filled(&ColorSetting::neutral().with_temp(3000), 256, &options);
+        let (exact, _, _) = filled_temp(3000, 256);
+        for (d, e) in r.iter().zip(exact.iter()) {
+            assert!((*d as f64 - *e as f64).abs() <= DITHER_STEP);
+        }
+    }
+
+    #[test]
+    fn scaled_neutral_ramp_is_identity() {
+        let (mut r, mut g, mut b) = (vec![0; 256], vec![0; 256], vec![0; 256]);
+        fill_scaled(
+            &mut r,
+            &mut g,
+            &mut b,
+            &ColorSetting::neutral(),
+            RampDepth::new(8).unwrap(),
+            &FillOptions::default(),
+        );
+        let identity = (0..256).collect::<Vec<u16>>();
+        assert_eq!(r, identity);
+        assert_eq!(g, identity);
+        assert_eq!(b, identity);
+    }
+
+    #[test]
+    fn scaled_ramps_stay_within_depth() {
+        let setting = ColorSetting::neutral().with_temp(3000);
+        for bits in [6, 8, 10] {
+            let (mut r, mut g, mut b) = (vec![0; 1024], vec![0; 1024], vec![0; 1024]);
+            fill_scaled(
+                &mut r,
+                &mut g,
+                &mut b,
+                &setting,
+                RampDepth::new(bits).unwrap(),
+                &FillOptions::default(),
+            );
+            let max = (1 << bits) - 1;
+            assert!(r.iter().chain(&g).chain(&b).all(|&v| v <= max));
+        }
+    }
+
+    #[test]
+    fn scaled_to_16_bits_matches_fill() {
+        let (mut r, mut g, mut b) = (vec![0; 256], vec![0; 256], vec![0; 256]);
+        fill_scaled(
+            &mut r,
+            &mut g,
+            &mut b,
+            &ColorSetting::neutral().with_temp(3000),
+            RampDepth::FULL,
+            &FillOptions::default(),
+        );
+        assert_eq!((r, g, b), filled_temp(3000, 256));
+    }
+
+    #[test]
+    fn ramp_depths_are_checked() {
+        assert_eq!(RampDepth::new(16).unwrap(), RampDepth::FULL);
+        assert_eq!(RampDepth::new(1).unwrap().bits(), 1);
+        for bits in [0, 17, 32] {
+            let e = RampDepth::new(bits).unwrap_err();
+            assert!(e.to_string().starts_with("Ramp depth must be between"));
+        }
+    }
+
+    #[test]
+    fn identity_fills_every_ramp() {
+        let (mut r, mut g, mut b) = (vec![1; 256], vec![1; 1024], vec![]);
+        fill_identity(&mut r, &mut g, &mut b);
+        assert_eq!(r, identity_ramp(256));
+        assert_eq!(g, identity_ramp(1024));
+    }
+
+    #[test]
+    fn neutral_temperature_is_identity() {
+        let identity = identity_ramp(256);
+        let (r, g, b) = filled_temp(6500, 256);
+        assert_eq!(r, identity);
+        assert_eq!(g, identity);
+        assert_eq!(b, identity);
+    }
+
+    #[test]
+    fn boundary_temperatures_stay_in_table() {
+        assert_eq!(white_point(1000), [1.0, 0.18172716, 0.0]);
+        assert_eq!(white_point(25000), [0.62774186, 0.75306977, 1.0]);
+        filled_temp(1000, 256);
+        filled_temp(25000, 256);
+    }
+
+    #[test]
+    fn white_points_match_redshift() {
+        assert_eq!(white_point(3000), [1.0, 0.71976951, 0.42860152]);
+        assert_eq!(white_point(4500), [1.0, 0.86860704, 0.73688797]);
+        assert_eq!(white_point(6500), [1.0, 1.0, 1.0]);
+        assert_eq!(white_point(10000), [0.78988728, 0.86491137, 1.0]);
+    }
+
+    #[test]
+    fn white_points_between_entries_are_interpolated() {
+        let [r, g, b] = white_point(3050);
+        assert_eq!(r, 1.0);
+        assert!((g - (0.71976951 + 0.73288760) / 2.0).abs() < 1e-9);
+        assert!((b - (0.42860152 + 0.45366838) / 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn out_of_table_temperatures_are_clamped() {
+        assert_eq!(white_point(500), white_point(1000));
+        assert_eq!(white_point(30000), white_point(25100));
+        filled_temp(0, 256);
+        filled_temp(30000, 256);
+    }
+}
@@ -0,0 +1,79 @@
+use super::Result;
+
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Settings read from the configuration file
+///
+/// Every field is optional, whatever is missing keeps its default.
+/// Values are validated just like the matching command-line flags.
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Color temperature, like `-S`
+    pub temp: Option<i32>,
+
+    /// Brightness, like `-b`
+    pub brightness: Option<f64>,
+
+    /// Gamma as a single value or `R:G:B`, like `-g`
+    pub gamma: Option<String>,
+
+    /// Gamma adjustment method, like `-m`
+    pub method: Option<String>,
+}
+
+/// Location of the configuration file
+/// (`~/.config/redshift-minimal-rs/config.toml` on Linux)
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("redshift-minimal-rs").join("config.toml"))
+}
+
+/// Load the configuration from the given file
+///
+/// A missing file is not an error, it just gives the empty configuration.
+pub fn load_from_path(path: &Path) -> Result<Config> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("Failed to read {}: {e}", path.display()).into()),
+    };
+
+    toml::from_str(&content).map_err(|e| format!("Invalid config {}: {e}", path.display()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_file_is_empty_config() {
+        let config = load_from_path(Path::new("/nonexistent/config.toml")).unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            temp = 4500
+            brightness = 0.8
+            gamma = "0.9:0.9:1.0"
+            method = "randr"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.temp, Some(4500));
+        assert_eq!(config.brightness, Some(0.8));
+        assert_eq!(config.gamma.as_deref(), Some("0.9:0.9:1.0"));
+        assert_eq!(config.method.as_deref(), Some("randr"));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("temprature = 4500").is_err());
+    }
+}
@@ -0,0 +1,341 @@
+use crate::colorramp::{self, FillOptions};
+use crate::transition;
+
+use super::GammaMethod;
+use super::GammaOptions;
+use super::OutputInfo;
+use super::OutputRamps;
+use super::Result;
+use std::error::Error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+// Structures and ioctl requests from the kernel's drm_mode.h
+#[repr(C)]
+#[derive(Default)]
+struct DrmModeCardRes {
+    fb_id_ptr: u64,
+    crtc_id_ptr: u64,
+    connector_id_ptr: u64,
+    encoder_id_ptr: u64,
+    count_fbs: u32,
+    count_crtcs: u32,
+    count_connectors: u32,
+    count_encoders: u32,
+    min_width: u32,
+    max_width: u32,
+    min_height: u32,
+    max_height: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct DrmModeCrtc {
+    set_connectors_ptr: u64,
+    count_connectors: u32,
+    crtc_id: u32,
+    fb_id: u32,
+    x: u32,
+    y: u32,
+    gamma_size: u32,
+    mode_valid: u32,
+    /// struct drm_mode_modeinfo, which is not needed here
+    mode: [u32; 17],
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct DrmModeCrtcLut {
+    crtc_id: u32,
+    gamma_size: u32,
+    red: u64,
+    green: u64,
+    blue: u64,
+}
+
+const fn drm_iowr<T>(nr: u32) -> libc::Ioctl {
+    // _IOC(_IOC_READ | _IOC_WRITE, DRM_IOCTL_BASE, nr, sizeof(T))
+    ((3 << 30) | ((std::mem::size_of::<T>() as u32) << 16) | ((b'd' as u32) << 8) | nr)
+        as libc::Ioctl
+}
+
+const DRM_IOCTL_MODE_GETRESOURCES: libc::Ioctl = drm_iowr::<DrmModeCardRes>(0xa0);
+const DRM_IOCTL_MODE_GETCRTC: libc::Ioctl = drm_iowr::<DrmModeCrtc>(0xa1);
+const DRM_IOCTL_MODE_GETGAMMA: libc::Ioctl = drm_iowr::<DrmModeCrtcLut>(0xa4);
+const DRM_IOCTL_MODE_SETGAMMA: libc::Ioctl = drm_iowr::<DrmModeCrtcLut>(0xa5);
+
+/// Wrapper for DRM errors
+#[derive(Debug)]
+pub enum DrmError {
+    Io(io::Error),
+    OpenCard(usize, io::Error),
+    CrtcNotFound(usize, usize),
+}
+
+impl DrmError {
+    fn io(e: io::Error) -> Box<dyn Error> {
+        Box::new(DrmError::Io(e)) as Box<dyn Error>
+    }
+
+    fn open_card(card: usize, e: io::Error) -> Box<dyn Error> {
+        Box::new(DrmError::OpenCard(card, e)) as Box<dyn Error>
+    }
+
+    fn crtc_not_found(crtc: usize, count: usize) -> Box<dyn Error> {
+        Box::new(DrmError::CrtcNotFound(crtc, count)) as Box<dyn Error>
+    }
+}
+
+impl fmt::Display for DrmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::DrmError::*;
+        // The wrapped io errors are reported through source()
+        match *self {
+            Io(..) => write!(f, "DRM request failed"),
+            OpenCard(card, ..) => write!(f, "Failed to open /dev/dri/card{card}"),
+            CrtcNotFound(crtc, count) => {
+                write!(f, "CRTC {crtc} does not exist (card has {count} CRTCs)")
+            }
+        }
+    }
+}
+
+impl Error for DrmError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DrmError::Io(ref e) | DrmError::OpenCard(_, ref e) => Some(e),
+            DrmError::CrtcNotFound(..) => None,
+        }
+    }
+}
+
+struct Crtc {
+    /// The id of the CRTC (gotten from DRM)
+    id: u32,
+
+    /// The ramp size.
+    ramp_size: u32,
+
+    /// The initial gamma ramp values - used for restore
+    saved_ramps: (Vec<u16>, Vec<u16>, Vec<u16>),
+
+    /// A scratchpad for color computation - it saves the cost of
+    /// allocating three new arrays whenever set_temperature() is
+    /// called.
+    scratch: (Vec<u16>, Vec<u16>, Vec<u16>),
+}
+
+/// Wrapping struct for DRM state
+pub struct DrmState {
+    card: File,
+    crtc_nums: Vec<usize>,
+    crtcs: Vec<Crtc>,
+
+    /// How the gamma ramps are computed
+    fill_options: FillOptions,
+}
+
+/// Issue a DRM ioctl on the given card
+fn drm_ioctl<T>(card: &File, request: libc::Ioctl, arg: &mut T) -> Result<()> {
+    let ret = unsafe { libc::ioctl(card.as_raw_fd(), request, arg as *mut T) };
+    if ret == -1 {
+        return Err(DrmError::io(io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// Set the gamma ramps of the CRTC with the given id
+fn set_gamma(card: &File, id: u32, r: &[u16], g: &[u16], b: &[u16]) -> Result<()> {
+    let mut lut = DrmModeCrtcLut {
+        crtc_id: id,
+        gamma_size: r.len() as u32,
+        red: r.as_ptr() as u64,
+        green: g.as_ptr() as u64,
+        blue: b.as_ptr() as u64,
+    };
+    drm_ioctl(card, DRM_IOCTL_MODE_SETGAMMA, &mut lut)
+}
+
+impl DrmState {
+    fn init(options: &GammaOptions) -> Result<DrmState> {
+        let card = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!("/dev/dri/card{}", options.card))
+            .map_err(|e| DrmError::open_card(options.card, e))?;
+
+        Ok(DrmState {
+            card,
+            crtc_nums: options.crtcs.clone(),
+            crtcs: vec![],
+            fill_options: options.fill.clone(),
+        })
+    }
+
+    /// Get the ids of all CRTCs of the card
+    fn crtc_ids(&self) -> Result<Vec<u32>> {
+        let mut res = DrmModeCardRes::default();
+        drm_ioctl(&self.card, DRM_IOCTL_MODE_GETRESOURCES, &mut res)?;
+
+        let mut ids = vec![0u32; res.count_crtcs as usize];
+        let mut res = DrmModeCardRes {
+            crtc_id_ptr: ids.as_mut_ptr() as u64,
+            count_crtcs: ids.len() as u32,
+            ..Default::default()
+        };
+        drm_ioctl(&self.card, DRM_IOCTL_MODE_GETRESOURCES, &mut res)?;
+
+        ids.truncate(res.count_crtcs as usize);
+        Ok(ids)
+    }
+}
+
+impl GammaMethod for DrmState {
+    fn name(&self) -> &'static str {
+        "drm"
+    }
+
+    //
+    // Restore saved gamma ramps
+    //
+    fn restore(&self) -> Result<()> {
+        for crtc in self.crtcs.iter() {
+            set_gamma(
+                &self.card,
+                crtc.id,
+                &crtc.saved_ramps.0[..],
+                &crtc.saved_ramps.1[..],
+                &crtc.saved_ramps.2[..],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn adjusted_outputs(&self) -> Vec<OutputInfo> {
+        self.crtcs
+            .iter()
+            .map(|crtc| OutputInfo {
+                id: crtc.id,
+                ramp_size: crtc.ramp_size,
+            })
+            .collect()
+    }
+
+    fn saved_ramps(&self) -> Vec<OutputRamps> {
+        self.crtcs
+            .iter()
+            .map(|crtc| OutputRamps {
+                output: OutputInfo {
+                    id: crtc.id,
+                    ramp_size: crtc.ramp_size,
+                },
+                red: crtc.saved_ramps.0.clone(),
+                green: crtc.saved_ramps.1.clone(),
+                blue: crtc.saved_ramps.2.clone(),
+            })
+            .collect()
+    }
+
+    fn set_temperature(&mut self, setting: &transition::ColorSetting) -> Result<()> {
+        for crtc in self.crtcs.iter_mut() {
+            // Nothing to adjust on disabled CRTCs
+            if crtc.ramp_size == 0 {
+                continue;
+            }
+
+            let (ref mut r, ref mut g, ref mut b) = crtc.scratch;
+
+            colorramp::fill_identity(r, g, b);
+
+            // Compute new gamma ramps
+            colorramp::fill(
+                &mut r[..],
+                &mut g[..],
+                &mut b[..],
+                setting,
+                crtc.ramp_size as usize,
+                &self.fill_options,
+            );
+
+            // Set the gamma ramp
+            set_gamma(&self.card, crtc.id, r, g, b)?;
+        }
+        Ok(())
+    }
+
+    /// Find initial information on the selected CRTCs
+    fn start(&mut self) -> Result<()> {
+        let mut ids = self.crtc_ids()?;
+        verbose!("Found {} CRTCs", ids.len());
+
+        if let Some(&n) = self.crtc_nums.iter().find(|&&n| n >= ids.len()) {
+            return Err(DrmError::crtc_not_found(n, ids.len()));
+        }
+        if !self.crtc_nums.is_empty() {
+            ids = self.crtc_nums.iter().map(|&n| ids[n]).collect();
+        }
+
+        self.crtcs = Vec::with_capacity(ids.len());
+
+        // Save size and gamma ramps of all CRTCs
+        for id in ids {
+            let mut info = DrmModeCrtc {
+                crtc_id: id,
+                ..Default::default()
+            };
+            drm_ioctl(&self.card, DRM_IOCTL_MODE_GETCRTC, &mut info)?;
+
+            let ramp_size = info.gamma_size;
+
+            // Disabled CRTCs can report an empty ramp
+            if ramp_size == 0 {
+                verbose!("Skipping CRTC {id} with gamma ramp size 0");
+                continue;
+            }
+            verbose!("CRTC {id}: gamma ramp size {ramp_size}");
+            let mut red = vec![0u16; ramp_size as usize];
+            let mut green = vec![0u16; ramp_size as usize];
+            let mut blue = vec![0u16; ramp_size as usize];
+
+            let mut lut = DrmModeCrtcLut {
+                crtc_id: id,
+                gamma_size: ramp_size,
+                red: red.as_mut_ptr() as u64,
+                green: green.as_mut_ptr() as u64,
+                blue: blue.as_mut_ptr() as u64,
+            };
+            drm_ioctl(&self.card, DRM_IOCTL_MODE_GETGAMMA, &mut lut)?;
+
+            self.crtcs.push(Crtc {
+                id,
+                ramp_size,
+                saved_ramps: (red.clone(), green.clone(), blue.clone()),
+                scratch: (red, green, blue),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The init function
+pub fn init(options: &GammaOptions) -> Result<Box<dyn GammaMethod>> {
+    DrmState::init(options).map(|d| Box::new(d) as Box<dyn GammaMethod>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_expose_their_source() {
+        let e = DrmError::OpenCard(1, io::Error::from(io::ErrorKind::NotFound));
+        assert!(e.source().is_some());
+        assert_eq!(e.to_string(), "Failed to open /dev/dri/card1");
+
+        let e = DrmError::CrtcNotFound(3, 2);
+        assert!(e.source().is_none());
+        assert_eq!(e.to_string(), "CRTC 3 does not exist (card has 2 CRTCs)");
+    }
+}
@@ -0,0 +1,249 @@
+use crate::colorramp::{self, FillOptions, RampDepth};
+use crate::transition;
+
+use super::GammaMethod;
+use super::GammaOptions;
+use super::OutputInfo;
+use super::OutputRamps;
+use super::Result;
+use std::error::Error;
+use std::fmt;
+
+type CGDirectDisplayID = u32;
+type CGGammaValue = f32;
+type CGError = i32;
+
+const CG_ERROR_SUCCESS: CGError = 0;
+
+/// Upper bound for the number of displays to enumerate
+const MAX_DISPLAYS: u32 = 32;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGGetActiveDisplayList(
+        max_displays: u32,
+        active_displays: *mut CGDirectDisplayID,
+        display_count: *mut u32,
+    ) -> CGError;
+    fn CGDisplayGammaTableCapacity(display: CGDirectDisplayID) -> u32;
+    fn CGGetDisplayTransferByTable(
+        display: CGDirectDisplayID,
+        capacity: u32,
+        red_table: *mut CGGammaValue,
+        green_table: *mut CGGammaValue,
+        blue_table: *mut CGGammaValue,
+        sample_count: *mut u32,
+    ) -> CGError;
+    fn CGSetDisplayTransferByTable(
+        display: CGDirectDisplayID,
+        table_size: u32,
+        red_table: *const CGGammaValue,
+        green_table: *const CGGammaValue,
+        blue_table: *const CGGammaValue,
+    ) -> CGError;
+    fn CGDisplayRestoreColorSyncSettings();
+}
+
+/// Wrapper for CoreGraphics errors
+#[derive(Debug)]
+pub enum QuartzError {
+    /// A CoreGraphics call failed with the given CGError
+    CoreGraphics(&'static str, CGError),
+}
+
+impl QuartzError {
+    fn check(call: &'static str, err: CGError) -> Result<()> {
+        if err != CG_ERROR_SUCCESS {
+            return Err(Box::new(QuartzError::CoreGraphics(call, err)) as Box<dyn Error>);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for QuartzError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::QuartzError::*;
+        match *self {
+            CoreGraphics(call, err) => write!(f, "quartz error: {call} failed ({err})"),
+        }
+    }
+}
+
+impl Error for QuartzError {}
+
+struct Display {
+    /// The id of the display (gotten from CoreGraphics)
+    id: CGDirectDisplayID,
+
+    /// The ramp size.
+    ramp_size: u32,
+
+    /// The initial gamma tables - used for restore
+    saved_ramps: (Vec<f32>, Vec<f32>, Vec<f32>),
+
+    /// A scratchpad for color computation - it saves the cost of
+    /// allocating three new arrays whenever set_temperature() is
+    /// called.
+    scratch: (Vec<u16>, Vec<u16>, Vec<u16>),
+}
+
+/// Wrapping struct for Quartz state
+pub struct QuartzState {
+    displays: Vec<Display>,
+
+    /// How the gamma ramps are computed
+    fill_options: FillOptions,
+}
+
+impl QuartzState {
+    fn init(options: &GammaOptions) -> Result<QuartzState> {
+        Ok(QuartzState {
+            displays: vec![],
+            fill_options: options.fill.clone(),
+        })
+    }
+}
+
+impl GammaMethod for QuartzState {
+    fn name(&self) -> &'static str {
+        "quartz"
+    }
+
+    //
+    // Restore saved gamma tables
+    //
+    fn restore(&self) -> Result<()> {
+        for display in self.displays.iter() {
+            let (ref r, ref g, ref b) = display.saved_ramps;
+            QuartzError::check("CGSetDisplayTransferByTable", unsafe {
+                CGSetDisplayTransferByTable(
+                    display.id,
+                    display.ramp_size,
+                    r.as_ptr(),
+                    g.as_ptr(),
+                    b.as_ptr(),
+                )
+            })?;
+        }
+
+        // Reset anything else to the ColorSync profiles
+        unsafe { CGDisplayRestoreColorSyncSettings() };
+        Ok(())
+    }
+
+    fn adjusted_outputs(&self) -> Vec<OutputInfo> {
+        self.displays
+            .iter()
+            .map(|display| OutputInfo {
+                id: display.id,
+                ramp_size: display.ramp_size,
+            })
+            .collect()
+    }
+
+    /// The tables are converted back from floats
+    fn saved_ramps(&self) -> Vec<OutputRamps> {
+        let to_ramp = |table: &[f32]| -> Vec<u16> {
+            table
+                .iter()
+                .map(|&v| (v.clamp(0.0, 1.0) * u16::MAX as f32) as u16)
+                .collect()
+        };
+        self.displays
+            .iter()
+            .map(|display| OutputRamps {
+                output: OutputInfo {
+                    id: display.id,
+                    ramp_size: display.ramp_size,
+                },
+                red: to_ramp(&display.saved_ramps.0),
+                green: to_ramp(&display.saved_ramps.1),
+                blue: to_ramp(&display.saved_ramps.2),
+            })
+            .collect()
+    }
+
+    fn set_temperature(&mut self, setting: &transition::ColorSetting) -> Result<()> {
+        for display in self.displays.iter_mut() {
+            let (ref mut r, ref mut g, ref mut b) = display.scratch;
+
+            // Compute new gamma ramps
+            colorramp::fill_scaled(
+                &mut r[..],
+                &mut g[..],
+                &mut b[..],
+                setting,
+                RampDepth::FULL,
+                &self.fill_options,
+            );
+
+            // Quartz wants the tables as floats between 0 and 1
+            let to_table = |ramp: &[u16]| -> Vec<f32> {
+                ramp.iter().map(|&v| v as f32 / u16::MAX as f32).collect()
+            };
+            let (r, g, b) = (to_table(r), to_table(g), to_table(b));
+
+            // Set the gamma tables
+            QuartzError::check("CGSetDisplayTransferByTable", unsafe {
+                CGSetDisplayTransferByTable(
+                    display.id,
+                    display.ramp_size,
+                    r.as_ptr(),
+                    g.as_ptr(),
+                    b.as_ptr(),
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Find all active displays and save their gamma tables
+    fn start(&mut self) -> Result<()> {
+        let mut ids = vec![0 as CGDirectDisplayID; MAX_DISPLAYS as usize];
+        let mut count = 0;
+        QuartzError::check("CGGetActiveDisplayList", unsafe {
+            CGGetActiveDisplayList(MAX_DISPLAYS, ids.as_mut_ptr(), &mut count)
+        })?;
+        ids.truncate(count as usize);
+        verbose!("Found {} displays", ids.len());
+
+        self.displays = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            let capacity = unsafe { CGDisplayGammaTableCapacity(id) };
+            let mut red = vec![0.0; capacity as usize];
+            let mut green = vec![0.0; capacity as usize];
+            let mut blue = vec![0.0; capacity as usize];
+            let mut ramp_size = 0;
+
+            QuartzError::check("CGGetDisplayTransferByTable", unsafe {
+                CGGetDisplayTransferByTable(
+                    id,
+                    capacity,
+                    red.as_mut_ptr(),
+                    green.as_mut_ptr(),
+                    blue.as_mut_ptr(),
+                    &mut ramp_size,
+                )
+            })?;
+            red.truncate(ramp_size as usize);
+            green.truncate(ramp_size as usize);
+            blue.truncate(ramp_size as usize);
+
+            verbose!("Display {id}: gamma ramp size {ramp_size}");
+            let size = ramp_size as usize;
+            self.displays.push(Display {
+                id,
+                ramp_size,
+                saved_ramps: (red, green, blue),
+                scratch: (vec![0; size], vec![0; size], vec![0; size]),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The init function
+pub fn init(options: &GammaOptions) -> Result<Box<dyn GammaMethod>> {
+    QuartzState::init(options).map(|q| Box::new(q) as Box<dyn GammaMethod>)
+}
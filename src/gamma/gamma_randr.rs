@@ -1,9 +1,14 @@
-use crate::colorramp;
+use crate::colorramp::{self, FillOptions};
 use crate::transition;
 use xcb::{randr, x, Xid};
 
 use super::GammaMethod;
+use super::GammaOptions;
+use super::OutputInfo;
+use super::OutputRamps;
+use super::Rect;
 use super::Result;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 
@@ -11,19 +16,26 @@ const RANDR_MAJOR_VERSION: u32 = 1;
 const RANDR_MINOR_VERSION: u32 = 3;
 
 /// Wrapper for XCB and RandR errors
+#[derive(Debug)]
 pub enum RandrError {
     Generic(xcb::Error),
     Conn(xcb::ConnError),
     UnsupportedVersion(u32, u32),
+    CrtcNotFound(usize, usize),
+    ScreenNotFound(usize),
+    OutputNotFound(String, Vec<String>),
+    OutputDisabled(String),
+    NoCrtcs,
+    /// The CRTCs whose gamma ramps could not be set, with the errors
+    SetGamma(Vec<(u32, xcb::ProtocolError)>),
+    RampSizeMismatch(u32, u16, [usize; 3]),
 }
 
 impl RandrError {
     fn generic(e: xcb::Error) -> Box<dyn Error> {
         Box::new(RandrError::Generic(e)) as Box<dyn Error>
     }
-}
 
-impl RandrError {
     fn conn(e: xcb::ConnError) -> Box<dyn Error> {
         Box::new(RandrError::Conn(e)) as Box<dyn Error>
     }
@@ -31,34 +43,89 @@ impl RandrError {
     fn unsupported_version(major: u32, minor: u32) -> Box<dyn Error> {
         Box::new(RandrError::UnsupportedVersion(major, minor)) as Box<dyn Error>
     }
-}
 
-impl fmt::Display for RandrError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{self:?}")
+    fn crtc_not_found(crtc: usize, count: usize) -> Box<dyn Error> {
+        Box::new(RandrError::CrtcNotFound(crtc, count)) as Box<dyn Error>
+    }
+
+    fn screen_not_found(screen: usize) -> Box<dyn Error> {
+        Box::new(RandrError::ScreenNotFound(screen)) as Box<dyn Error>
+    }
+
+    fn output_not_found(output: &str, available: Vec<String>) -> Box<dyn Error> {
+        Box::new(RandrError::OutputNotFound(output.to_string(), available)) as Box<dyn Error>
+    }
+
+    fn output_disabled(output: &str) -> Box<dyn Error> {
+        Box::new(RandrError::OutputDisabled(output.to_string())) as Box<dyn Error>
+    }
+
+    fn no_crtcs() -> Box<dyn Error> {
+        Box::new(RandrError::NoCrtcs) as Box<dyn Error>
+    }
+
+    fn set_gamma(failed: Vec<(u32, xcb::ProtocolError)>) -> Box<dyn Error> {
+        Box::new(RandrError::SetGamma(failed)) as Box<dyn Error>
+    }
+
+    fn ramp_size_mismatch(crtc: u32, ramp_size: u16, lengths: [usize; 3]) -> Box<dyn Error> {
+        Box::new(RandrError::RampSizeMismatch(crtc, ramp_size, lengths)) as Box<dyn Error>
     }
 }
 
-impl fmt::Debug for RandrError {
+impl fmt::Display for RandrError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::RandrError::*;
+        // The wrapped xcb errors are reported through source()
         match *self {
-            Generic(ref e) => write!(f, "randr error: {}", e),
-            Conn(xcb::ConnError::Connection) => write!(
-                f,
-                "xcb connection errors because of socket, pipe or other stream errors"
-            ),
-            Conn(ref c) => write!(f, "{c:?}"),
+            Generic(..) => write!(f, "randr request failed"),
+            Conn(..) => write!(f, "failed to connect to the X server"),
             UnsupportedVersion(major, minor) => {
                 write!(f, "Unsupported RandR version ({major}.{minor})")
             }
+            CrtcNotFound(crtc, count) => {
+                write!(f, "CRTC {crtc} does not exist (screen has {count} CRTCs)")
+            }
+            ScreenNotFound(screen) => write!(f, "Screen {screen} does not exist"),
+            OutputNotFound(ref output, ref available) => write!(
+                f,
+                "Output {output} does not exist (available: {})",
+                available.join(", ")
+            ),
+            OutputDisabled(ref output) => write!(f, "Output {output} is disabled"),
+            NoCrtcs => write!(
+                f,
+                "No CRTCs found, is a display connected? (use --method dummy for testing)"
+            ),
+            SetGamma(ref failed) => {
+                let crtcs = failed
+                    .iter()
+                    .map(|(crtc, _)| crtc.to_string())
+                    .collect::<Vec<_>>();
+                let plural = if crtcs.len() == 1 { "" } else { "s" };
+                write!(
+                    f,
+                    "failed to set the gamma ramps of CRTC{plural} {}",
+                    crtcs.join(", ")
+                )
+            }
+            RampSizeMismatch(crtc, ramp_size, [r, g, b]) => write!(
+                f,
+                "CRTC {crtc} reported gamma ramps of sizes {r}, {g} and {b} for a ramp size of {ramp_size}"
+            ),
         }
     }
 }
 
 impl Error for RandrError {
-    fn description(&self) -> &str {
-        "RandR error"
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            RandrError::Generic(ref e) => Some(e),
+            RandrError::Conn(ref c) => Some(c),
+            // Only one of the errors can be the source
+            RandrError::SetGamma(ref failed) => failed.first().map(|(_, e)| e as _),
+            _ => None,
+        }
     }
 }
 
@@ -66,10 +133,14 @@ struct Crtc {
     /// The id of CRTC (gotten from XCB)
     id: u32,
 
+    /// The index of the CRTC in the screen resources, as used by --crtc
+    index: usize,
+
     /// The ramp size.
     ramp_size: u16,
 
-    /// The initial gamma ramp values - used for restore
+    /// The initial gamma ramp values - used for restore. They are
+    /// captured once in start() and never overwritten afterwards.
     saved_ramps: (Vec<u16>, Vec<u16>, Vec<u16>),
 
     /// A scratchpad for color computation - it saves the cost of
@@ -81,89 +152,433 @@ struct Crtc {
 /// Wrapping struct for RandR state
 pub struct RandrState {
     conn: xcb::Connection,
-    window_dummy: x::Window,
+    /// A dummy window on each of the adjusted screens, to get their
+    /// screen resources through
+    windows_dummy: Vec<x::Window>,
     crtcs: Vec<Crtc>,
+
+    /// Indices of the CRTCs to adjust, all of them if empty
+    crtc_nums: Vec<usize>,
+
+    /// Temperatures overriding the one of the color setting, by index
+    crtc_temps: HashMap<usize, i32>,
+
+    /// Names of the outputs whose CRTCs to adjust, in addition to
+    /// `crtc_nums`
+    output_names: Vec<String>,
+
+    /// Compute the gamma ramps without sending them
+    dry_run: bool,
+
+    /// How the gamma ramps are computed
+    fill_options: FillOptions,
 }
 
 impl RandrState {
-    fn init() -> Result<RandrState> {
+    fn init(options: &GammaOptions) -> Result<RandrState> {
         let (conn, screen_num) = xcb::Connection::connect(None).map_err(RandrError::conn)?;
+        let screen_num = options.screen.unwrap_or(screen_num as usize);
 
-        query_version(&conn)?;
+        query_version(&conn, options.skip_version_check)?;
 
-        let window_dummy = {
+        let windows_dummy = {
             let setup = conn.get_setup();
-            let screen = setup.roots().nth(screen_num as usize).unwrap();
-            let window_dummy = conn.generate_id();
-
-            conn.send_request(&x::CreateWindow {
-                depth: x::COPY_FROM_PARENT as u8,
-                wid: window_dummy,
-                parent: screen.root(),
-                x: 0,
-                y: 0,
-                width: 1,
-                height: 1,
-                border_width: 0,
-                class: x::WindowClass::InputOutput,
-                visual: screen.root_visual(),
-                value_list: &[],
-            });
+            let screens = if options.all_screens {
+                setup.roots().collect::<Vec<_>>()
+            } else {
+                let screen = setup
+                    .roots()
+                    .nth(screen_num)
+                    .ok_or_else(|| RandrError::screen_not_found(screen_num))?;
+                vec![screen]
+            };
+
+            let mut windows_dummy = Vec::with_capacity(screens.len());
+            for screen in screens {
+                let window_dummy = conn.generate_id();
+
+                conn.send_request(&x::CreateWindow {
+                    depth: x::COPY_FROM_PARENT as u8,
+                    wid: window_dummy,
+                    parent: screen.root(),
+                    x: 0,
+                    y: 0,
+                    width: 1,
+                    height: 1,
+                    border_width: 0,
+                    class: x::WindowClass::InputOutput,
+                    visual: screen.root_visual(),
+                    value_list: &[],
+                });
+
+                // Get notified when outputs are plugged or reconfigured
+                conn.send_request(&randr::SelectInput {
+                    window: window_dummy,
+                    enable: randr::NotifyMask::SCREEN_CHANGE | randr::NotifyMask::CRTC_CHANGE,
+                });
+                windows_dummy.push(window_dummy);
+            }
 
             conn.flush()?;
-            window_dummy
+            windows_dummy
         };
 
         Ok(RandrState {
             conn,
-            window_dummy,
+            windows_dummy,
             crtcs: vec![],
+            crtc_nums: options.crtcs.clone(),
+            crtc_temps: options.crtc_temps.clone(),
+            output_names: options.outputs.clone(),
+            dry_run: options.dry_run,
+            fill_options: options.fill.clone(),
         })
     }
 
     // Set the temperature for the indicated CRTC
     fn set_crtc_temperatures(&mut self, setting: &transition::ColorSetting) -> Result<()> {
+        let mut cookies = Vec::with_capacity(self.crtcs.len());
         for crtc in self.crtcs.iter_mut() {
-            let (ref mut r, ref mut g, ref mut b) = crtc.scratch;
+            // The scratchpad must match the ramp size last queried
+            let size = crtc.ramp_size as usize;
+            if crtc.scratch.0.len() != size {
+                crtc.scratch = (vec![0; size], vec![0; size], vec![0; size]);
+            }
 
-            let u16_max1 = u16::max_value() as f64 + 1.0;
-            let ramp_size = crtc.ramp_size as f64;
-            for i in 0..r.len() {
-                let v = ((i as f64 / ramp_size) * u16_max1) as u16;
-                r[i] = v;
-                g[i] = v;
-                b[i] = v;
+            // Nothing to adjust on disabled CRTCs
+            if crtc.ramp_size == 0 {
+                continue;
             }
 
+            let (ref mut r, ref mut g, ref mut b) = crtc.scratch;
+
+            colorramp::fill_identity(r, g, b);
+
+            let setting = match self.crtc_temps.get(&crtc.index) {
+                Some(&temp) => setting.with_temp(temp),
+                None => *setting,
+            };
+
             // Compute new gamma ramps
             colorramp::fill(
                 &mut r[..],
                 &mut g[..],
                 &mut b[..],
-                setting,
+                &setting,
                 crtc.ramp_size as usize,
+                &self.fill_options,
             );
 
-            // Set the gamma ramp
-            unsafe {
-                self.conn.send_request(&randr::SetCrtcGamma {
+            if self.dry_run {
+                verbose!(
+                    "Would set CRTC {} gamma ramps of size {} (last values {}, {}, {})",
+                    crtc.id,
+                    crtc.ramp_size,
+                    r[r.len() - 1],
+                    g[g.len() - 1],
+                    b[b.len() - 1]
+                );
+                continue;
+            }
+
+            // Set the gamma ramp. The requests of all the CRTCs go out
+            // together, so that the outputs change at the same time.
+            let cookie = unsafe {
+                self.conn.send_request_checked(&randr::SetCrtcGamma {
                     crtc: xcb::XidNew::new(crtc.id),
                     red: &r[..],
                     green: &g[..],
                     blue: &b[..],
+                })
+            };
+            cookies.push((crtc.id, cookie));
+        }
+
+        if cookies.is_empty() {
+            return Ok(());
+        }
+        self.conn.flush()?;
+
+        // Only the first check waits for the server. Every CRTC is
+        // checked, so that all of the ones that failed are reported.
+        let failed = cookies
+            .into_iter()
+            .filter_map(|(id, cookie)| self.conn.check_request(cookie).err().map(|e| (id, e)))
+            .collect::<Vec<_>>();
+        if !failed.is_empty() {
+            return Err(RandrError::set_gamma(failed));
+        }
+        Ok(())
+    }
+
+    /// Where the adjusted CRTCs are on the screen, by CRTC id
+    ///
+    /// CRTCs whose geometry cannot be queried are left out.
+    pub fn crtc_geometry(&self) -> Vec<(u32, Rect)> {
+        self.crtcs
+            .iter()
+            .filter_map(|crtc| {
+                let req = self.conn.send_request(&randr::GetCrtcInfo {
+                    crtc: unsafe { xcb::XidNew::new(crtc.id) },
+                    config_timestamp: x::CURRENT_TIME,
+                });
+                let info = self.conn.wait_for_reply(req).ok()?;
+                Some((
+                    crtc.id,
+                    Rect {
+                        x: info.x(),
+                        y: info.y(),
+                        width: info.width(),
+                        height: info.height(),
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// Get the list of CRTCs of the screens, one screen after the other
+    fn screen_crtcs(&self) -> Result<Vec<randr::Crtc>> {
+        let mut crtcs = vec![];
+        for &window in self.windows_dummy.iter() {
+            let req = self
+                .conn
+                .send_request(&randr::GetScreenResources { window });
+
+            let reply = self.conn.wait_for_reply(req).map_err(RandrError::generic)?;
+            crtcs.extend_from_slice(reply.crtcs());
+        }
+        Ok(crtcs)
+    }
+
+    /// Find the indices of the CRTCs driving the requested outputs
+    fn output_crtc_indices(&self, crtcs: &[randr::Crtc]) -> Result<Vec<usize>> {
+        let mut outputs = HashMap::new();
+        for &window in self.windows_dummy.iter() {
+            let req = self
+                .conn
+                .send_request(&randr::GetScreenResources { window });
+            let resources = self.conn.wait_for_reply(req).map_err(RandrError::generic)?;
+
+            for &output in resources.outputs() {
+                let req = self.conn.send_request(&randr::GetOutputInfo {
+                    output,
+                    config_timestamp: resources.config_timestamp(),
                 });
+                let info = self.conn.wait_for_reply(req).map_err(RandrError::generic)?;
+                outputs.insert(
+                    String::from_utf8_lossy(info.name()).into_owned(),
+                    info.crtc(),
+                );
+            }
+        }
+
+        self.output_names
+            .iter()
+            .map(|name| {
+                let crtc = outputs.get(name).ok_or_else(|| {
+                    let mut available = outputs.keys().cloned().collect::<Vec<_>>();
+                    available.sort_unstable();
+                    RandrError::output_not_found(name, available)
+                })?;
+                let index = crtcs
+                    .iter()
+                    .position(|c| !crtc.is_none() && c.resource_id() == crtc.resource_id())
+                    .ok_or_else(|| RandrError::output_disabled(name))?;
+                verbose!("Output {name} is driven by CRTC {index}");
+                Ok(index)
+            })
+            .collect()
+    }
+
+    /// Save size and gamma ramps of all selected CRTCs
+    ///
+    /// CRTCs that are already known keep the ramps saved when they were
+    /// first seen, as their current ones are the adjusted ones.
+    fn update_crtcs(&mut self, crtcs: &[randr::Crtc]) -> Result<()> {
+        let mut known = std::mem::take(&mut self.crtcs);
+        self.crtcs = Vec::with_capacity(crtcs.len());
+
+        for (i, crtc) in crtcs.iter().enumerate() {
+            if !self.crtc_nums.is_empty()
+                && !self.crtc_nums.contains(&i)
+                && !self.crtc_temps.contains_key(&i)
+            {
+                continue;
             }
 
-            // Save the new gamma ramps
-            crtc.saved_ramps = (r.clone(), g.clone(), b.clone());
+            if let Some(known_crtc) = take_known(&mut known, crtc.resource_id(), i) {
+                self.crtcs.push(known_crtc);
+                continue;
+            }
+
+            let ramp_size = query_ramp_size(&self.conn, crtc.resource_id())?;
+
+            // Disabled or disconnected CRTCs can report an empty ramp
+            if ramp_size == 0 {
+                verbose!(
+                    "Skipping CRTC {} with gamma ramp size 0",
+                    crtc.resource_id()
+                );
+                continue;
+            }
+
+            let req = self.conn.send_request(&randr::GetCrtcGamma { crtc: *crtc });
 
-            self.conn.flush()?;
+            let reply = self.conn.wait_for_reply(req).map_err(RandrError::generic)?;
+
+            let red = reply.red().to_vec();
+            let green = reply.green().to_vec();
+            let blue = reply.blue().to_vec();
+            check_ramp_lengths(crtc.resource_id(), ramp_size, [&red, &green, &blue])?;
+
+            verbose!("CRTC {}: gamma ramp size {ramp_size}", crtc.resource_id());
+            if [&red, &green, &blue].iter().any(|ramp| !is_identity(ramp)) {
+                verbose!(
+                    "CRTC {}: gamma ramps are already adjusted, by an earlier run or another program",
+                    crtc.resource_id()
+                );
+            }
+            self.crtcs.push(Crtc {
+                id: crtc.resource_id(),
+                index: i,
+                ramp_size,
+                saved_ramps: (red.clone(), green.clone(), blue.clone()),
+                scratch: (red, green, blue),
+            });
         }
         Ok(())
     }
+
+    /// Query the ramp sizes of the known CRTCs again
+    ///
+    /// The ramp size can change when outputs are hotplugged, which is
+    /// only worth asking the server about after it notified a change.
+    fn refresh_ramp_sizes(&mut self) -> Result<()> {
+        for crtc in self.crtcs.iter_mut() {
+            let ramp_size = query_ramp_size(&self.conn, crtc.id)?;
+            if ramp_size != crtc.ramp_size {
+                verbose!(
+                    "CRTC {}: gamma ramp size changed from {} to {ramp_size}",
+                    crtc.id,
+                    crtc.ramp_size
+                );
+                crtc.ramp_size = ramp_size;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether any screen or CRTC change was notified since the last call
+    fn outputs_changed(&self) -> Result<bool> {
+        let mut changed = false;
+        while let Some(event) = self.conn.poll_for_event().map_err(RandrError::generic)? {
+            if let xcb::Event::RandR(..) = event {
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// Take the CRTC with the given id out of the known ones, now at the
+/// given index of the screen resources
+///
+/// The index can change when outputs are hotplugged, and the
+/// temperatures of `--crtc N=TEMP` are looked up by it.
+fn take_known(known: &mut Vec<Crtc>, id: u32, index: usize) -> Option<Crtc> {
+    let pos = known.iter().position(|c| c.id == id)?;
+    let mut crtc = known.swap_remove(pos);
+    crtc.index = index;
+    Some(crtc)
+}
+
+/// Whether the error is a failure to connect to the X server
+pub(super) fn is_connection_error(e: &(dyn Error + 'static)) -> bool {
+    matches!(e.downcast_ref::<RandrError>(), Some(RandrError::Conn(..)))
+}
+
+/// Whether setting the gamma ramps failed because of a stale ramp size
+fn is_ramp_size_error(e: &(dyn Error + 'static)) -> bool {
+    match e.downcast_ref::<RandrError>() {
+        Some(RandrError::SetGamma(failed)) => failed.iter().any(|(_, e)| {
+            matches!(
+                e,
+                xcb::ProtocolError::X(x::Error::Length(..) | x::Error::Value(..), _)
+            )
+        }),
+        _ => false,
+    }
+}
+
+/// Whether the X server did not accept the connection (yet)
+///
+/// Other connection errors, like a missing or invalid `$DISPLAY`, are
+/// not worth retrying.
+pub(super) fn is_server_not_ready(e: &(dyn Error + 'static)) -> bool {
+    matches!(
+        e.downcast_ref::<RandrError>(),
+        Some(RandrError::Conn(xcb::ConnError::Connection))
+    )
+}
+
+/// Make sure the driver replied with a full ramp for every channel
+///
+/// `SetCrtcGamma` takes a single size for the three ramps, so they
+/// could not be restored otherwise.
+fn check_ramp_lengths(crtc: u32, ramp_size: u16, ramps: [&[u16]; 3]) -> Result<()> {
+    let lengths = ramps.map(|ramp| ramp.len());
+    if lengths.iter().any(|&len| len != ramp_size as usize) {
+        return Err(RandrError::ramp_size_mismatch(crtc, ramp_size, lengths));
+    }
+    Ok(())
 }
 
-fn query_version(conn: &xcb::Connection) -> Result<()> {
+fn query_ramp_size(conn: &xcb::Connection, crtc: u32) -> Result<u16> {
+    let req = conn.send_request(&randr::GetCrtcGammaSize {
+        crtc: unsafe { xcb::XidNew::new(crtc) },
+    });
+
+    let reply = conn.wait_for_reply(req).map_err(RandrError::generic)?;
+    Ok(reply.size())
+}
+
+/// Linearly resample a ramp to another number of entries
+///
+/// The first and last entries are kept as is.
+fn resample(ramp: &[u16], size: usize) -> Vec<u16> {
+    if ramp.len() < 2 || size < 2 {
+        return vec![ramp.first().copied().unwrap_or(0); size];
+    }
+
+    let scale = (ramp.len() - 1) as f64 / (size - 1) as f64;
+    (0..size)
+        .map(|i| {
+            let pos = i as f64 * scale;
+            let lo = pos.floor() as usize;
+            let hi = (lo + 1).min(ramp.len() - 1);
+            let frac = pos - lo as f64;
+            (ramp[lo] as f64 * (1.0 - frac) + ramp[hi] as f64 * frac).round() as u16
+        })
+        .collect()
+}
+
+/// Whether the ramp looks like an unadjusted one
+///
+/// Servers do not agree on the exact identity ramp, so entries may be
+/// off by up to two ramp steps.
+fn is_identity(ramp: &[u16]) -> bool {
+    let size = ramp.len() as f64;
+    let step = (u16::MAX as f64 + 1.0) / size;
+    ramp.iter()
+        .enumerate()
+        .all(|(i, &v)| (v as f64 - i as f64 * step).abs() <= 2.0 * step)
+}
+
+/// Make sure the server supports RandR 1.3
+///
+/// With `skip_check` an older version only gives a warning, as the
+/// gamma requests also work on some 1.2 servers.
+fn query_version(conn: &xcb::Connection, skip_check: bool) -> Result<()> {
     let req = randr::QueryVersion {
         major_version: RANDR_MAJOR_VERSION,
         minor_version: RANDR_MINOR_VERSION,
@@ -173,15 +588,18 @@ fn query_version(conn: &xcb::Connection) -> Result<()> {
     let cookie = conn.send_request(&req);
 
     let reply = conn.wait_for_reply(cookie).map_err(RandrError::generic)?;
+    let (major, minor) = (reply.major_version(), reply.minor_version());
+    verbose!("RandR version {major}.{minor}");
 
-    if reply.major_version() < RANDR_MAJOR_VERSION
-        || (reply.major_version() == RANDR_MAJOR_VERSION
-            && reply.minor_version() < RANDR_MINOR_VERSION)
+    if major < RANDR_MAJOR_VERSION || (major == RANDR_MAJOR_VERSION && minor < RANDR_MINOR_VERSION)
     {
-        return Err(RandrError::unsupported_version(
-            reply.major_version(),
-            reply.minor_version(),
-        ));
+        if !skip_check {
+            return Err(RandrError::unsupported_version(major, minor));
+        }
+        eprintln!(
+            "WARNING: {}, trying anyway",
+            RandrError::UnsupportedVersion(major, minor)
+        );
     }
 
     conn.flush()?;
@@ -190,73 +608,268 @@ fn query_version(conn: &xcb::Connection) -> Result<()> {
 }
 
 impl GammaMethod for RandrState {
+    fn name(&self) -> &'static str {
+        "randr"
+    }
+
     //
     // Restore saved gamma ramps
     //
     fn restore(&self) -> Result<()> {
+        // Nothing was changed, so there is nothing to restore
+        if self.dry_run {
+            return Ok(());
+        }
+
         for crtc in self.crtcs.iter() {
+            // The CRTC may be gone or have another ramp size after a
+            // hotplug, which must not keep the other CRTCs from being
+            // restored
+            let ramp_size = match query_ramp_size(&self.conn, crtc.id) {
+                Ok(0) | Err(_) => {
+                    eprintln!("WARNING: not restoring CRTC {} which is gone", crtc.id);
+                    continue;
+                }
+                Ok(size) => size as usize,
+            };
+
+            let (ref red, ref green, ref blue) = crtc.saved_ramps;
+            if red.is_empty() {
+                continue;
+            }
+
+            // Saved ramps of a different size would be rejected
+            let resampled;
+            let (red, green, blue) = if red.len() == ramp_size {
+                (&red[..], &green[..], &blue[..])
+            } else {
+                verbose!(
+                    "CRTC {}: resampling saved gamma ramps from size {} to {ramp_size}",
+                    crtc.id,
+                    red.len()
+                );
+                resampled = (
+                    resample(red, ramp_size),
+                    resample(green, ramp_size),
+                    resample(blue, ramp_size),
+                );
+                (&resampled.0[..], &resampled.1[..], &resampled.2[..])
+            };
+
             unsafe {
                 self.conn.send_request(&randr::SetCrtcGamma {
                     crtc: xcb::XidNew::new(crtc.id),
-                    red: &crtc.saved_ramps.0[..],
-                    green: &crtc.saved_ramps.1[..],
-                    blue: &crtc.saved_ramps.2[..],
+                    red,
+                    green,
+                    blue,
                 });
             }
-
-            self.conn.flush()?;
         }
+
+        self.conn.flush()?;
         Ok(())
     }
 
+    /// When outputs were hotplugged since the last call, the CRTCs are
+    /// looked up again first so that new outputs get adjusted too
     fn set_temperature(&mut self, setting: &transition::ColorSetting) -> Result<()> {
-        self.set_crtc_temperatures(setting)
+        if self.outputs_changed()? {
+            verbose!("Outputs changed, looking up the CRTCs again");
+            let crtcs = self.screen_crtcs()?;
+            self.update_crtcs(&crtcs)?;
+            self.refresh_ramp_sizes()?;
+        }
+
+        match self.set_crtc_temperatures(setting) {
+            // A ramp size change can go by without a notify, so the
+            // sizes are queried again before trying once more
+            Err(e) if is_ramp_size_error(&*e) => {
+                verbose!("{e}, querying the gamma ramp sizes again");
+                self.refresh_ramp_sizes()?;
+                self.set_crtc_temperatures(setting)
+            }
+            result => result,
+        }
+    }
+
+    fn adjusted_outputs(&self) -> Vec<OutputInfo> {
+        self.crtcs
+            .iter()
+            .filter(|crtc| crtc.ramp_size != 0)
+            .map(|crtc| OutputInfo {
+                id: crtc.id,
+                ramp_size: crtc.ramp_size as u32,
+            })
+            .collect()
+    }
+
+    fn saved_ramps(&self) -> Vec<OutputRamps> {
+        self.crtcs
+            .iter()
+            .filter(|crtc| crtc.ramp_size != 0)
+            .map(|crtc| OutputRamps {
+                output: OutputInfo {
+                    id: crtc.id,
+                    ramp_size: crtc.ramp_size as u32,
+                },
+                red: crtc.saved_ramps.0.clone(),
+                green: crtc.saved_ramps.1.clone(),
+                blue: crtc.saved_ramps.2.clone(),
+            })
+            .collect()
+    }
+
+    /// The ramps last set are still in the scratchpads
+    fn ramps_changed(&self) -> Result<bool> {
+        if self.dry_run {
+            return Ok(false);
+        }
+        for crtc in self.crtcs.iter().filter(|crtc| crtc.ramp_size != 0) {
+            let req = self.conn.send_request(&randr::GetCrtcGamma {
+                crtc: unsafe { xcb::XidNew::new(crtc.id) },
+            });
+            let reply = self.conn.wait_for_reply(req).map_err(RandrError::generic)?;
+
+            let (ref r, ref g, ref b) = crtc.scratch;
+            if reply.red() != &r[..] || reply.green() != &g[..] || reply.blue() != &b[..] {
+                verbose!("CRTC {}: gamma ramps changed since they were set", crtc.id);
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 
     /// Find initial information on all the CRTCs
     fn start(&mut self) -> Result<()> {
-        // Get list of CRTCs for the screen
+        let crtcs = self.screen_crtcs()?;
+        verbose!("Found {} CRTCs", crtcs.len());
 
-        let req = self.conn.send_request(&randr::GetScreenResources {
-            window: self.window_dummy,
-        });
+        // Headless servers can have none, adjusting nothing would look
+        // like it worked
+        if crtcs.is_empty() {
+            return Err(RandrError::no_crtcs());
+        }
 
-        let reply = self.conn.wait_for_reply(req).map_err(RandrError::generic)?;
+        if let Some(&n) = self
+            .crtc_nums
+            .iter()
+            .chain(self.crtc_temps.keys())
+            .find(|&&n| n >= crtcs.len())
+        {
+            return Err(RandrError::crtc_not_found(n, crtcs.len()));
+        }
 
-        let crtcs = reply.crtcs();
+        if !self.output_names.is_empty() {
+            let indices = self.output_crtc_indices(&crtcs)?;
+            self.crtc_nums.extend(indices);
+            self.crtc_nums.sort_unstable();
+            self.crtc_nums.dedup();
+        }
 
-        self.crtcs = Vec::with_capacity(crtcs.len() as usize);
+        self.update_crtcs(&crtcs)?;
+
+        if crate::log::is_verbose() {
+            for (id, rect) in self.crtc_geometry() {
+                verbose!(
+                    "CRTC {id}: {}x{} at {},{}",
+                    rect.width,
+                    rect.height,
+                    rect.x,
+                    rect.y
+                );
+            }
+        }
+        Ok(())
+    }
+}
 
-        // Save size and gamma ramps of all CRTCs
-        for crtc in crtcs {
-            let req = self
-                .conn
-                .send_request(&randr::GetCrtcGammaSize { crtc: *crtc });
+/// The init function
+pub fn init(options: &GammaOptions) -> Result<Box<dyn GammaMethod>> {
+    RandrState::init(options).map(|r| Box::new(r) as Box<dyn GammaMethod>)
+}
 
-            let reply = self.conn.wait_for_reply(req).map_err(RandrError::generic)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_expose_their_source() {
+        let e = RandrError::Conn(xcb::ConnError::ClosedParseErr);
+        assert!(e.source().is_some());
+        assert_eq!(e.to_string(), "failed to connect to the X server");
+
+        let e = RandrError::ScreenNotFound(2);
+        assert!(e.source().is_none());
+        assert_eq!(e.to_string(), "Screen 2 does not exist");
+
+        let e = RandrError::OutputNotFound("HDMI-2".into(), vec!["HDMI-1".into(), "eDP-1".into()]);
+        assert_eq!(
+            e.to_string(),
+            "Output HDMI-2 does not exist (available: HDMI-1, eDP-1)"
+        );
+
+        assert!(RandrError::NoCrtcs
+            .to_string()
+            .starts_with("No CRTCs found, is a display connected?"));
+    }
 
-            let ramp_size = reply.size();
+    #[test]
+    fn known_crtcs_follow_their_new_index() {
+        let crtc = |id, index| Crtc {
+            id,
+            index,
+            ramp_size: 256,
+            saved_ramps: (vec![1; 256], vec![1; 256], vec![1; 256]),
+            scratch: (vec![0; 256], vec![0; 256], vec![0; 256]),
+        };
+        let mut known = vec![crtc(63, 0), crtc(64, 1)];
 
-            let req = self.conn.send_request(&randr::GetCrtcGamma { crtc: *crtc });
+        // A hotplug swapped the CRTCs in the screen resources
+        let moved = take_known(&mut known, 64, 0).unwrap();
+        assert_eq!((moved.id, moved.index), (64, 0));
+        assert_eq!(moved.saved_ramps.0, vec![1; 256]);
+        let moved = take_known(&mut known, 63, 1).unwrap();
+        assert_eq!((moved.id, moved.index), (63, 1));
 
-            let reply = self.conn.wait_for_reply(req).map_err(RandrError::generic)?;
+        assert!(known.is_empty());
+        assert!(take_known(&mut known, 65, 2).is_none());
+    }
 
-            let red = reply.red().to_vec();
-            let green = reply.green().to_vec();
-            let blue = reply.blue().to_vec();
+    #[test]
+    fn mismatched_ramp_lengths_are_rejected() {
+        let (full, short) = (vec![0u16; 256], vec![0u16; 255]);
+        assert!(check_ramp_lengths(63, 256, [&full, &full, &full]).is_ok());
+        assert!(check_ramp_lengths(63, 1024, [&full, &full, &full]).is_err());
+
+        let e = check_ramp_lengths(63, 256, [&full, &short, &full]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "CRTC 63 reported gamma ramps of sizes 256, 255 and 256 for a ramp size of 256"
+        );
+    }
 
-            self.crtcs.push(Crtc {
-                id: crtc.resource_id(),
-                ramp_size,
-                saved_ramps: (red.clone(), green.clone(), blue.clone()),
-                scratch: (red.clone(), green.clone(), blue.clone()),
-            });
-        }
-        Ok(())
+    #[test]
+    fn ramps_are_resampled() {
+        let ramp = (0..256).map(|i| i * 257).collect::<Vec<u16>>();
+        let larger = resample(&ramp, 1024);
+        assert_eq!(larger.len(), 1024);
+        assert_eq!((larger[0], larger[1023]), (0, u16::MAX));
+        assert!(larger.windows(2).all(|w| w[0] <= w[1]));
+        assert!(is_identity(&larger));
+
+        assert_eq!(resample(&larger, 256), ramp);
+        assert_eq!(resample(&[7], 3), vec![7, 7, 7]);
+        assert!(resample(&ramp, 0).is_empty());
     }
-}
 
-/// The init function
-pub fn init() -> Result<Box<dyn GammaMethod>> {
-    RandrState::init().map(|r| Box::new(r) as Box<dyn GammaMethod>)
+    #[test]
+    fn identity_ramps_are_recognised() {
+        let ours = (0..256).map(|i| i * 256).collect::<Vec<u16>>();
+        let xorg = (0..256).map(|i| i * 257).collect::<Vec<u16>>();
+        assert!(is_identity(&ours));
+        assert!(is_identity(&xorg));
+
+        let dimmed = xorg.iter().map(|v| v / 2).collect::<Vec<u16>>();
+        assert!(!is_identity(&dimmed));
+    }
 }
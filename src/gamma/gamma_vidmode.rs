@@ -0,0 +1,232 @@
+use crate::colorramp::{self, FillOptions};
+use crate::transition;
+use xcb::xf86vidmode;
+
+use super::GammaMethod;
+use super::GammaOptions;
+use super::OutputInfo;
+use super::OutputRamps;
+use super::Result;
+use std::error::Error;
+use std::fmt;
+
+/// Wrapper for XCB and VidMode errors
+#[derive(Debug)]
+pub enum VidmodeError {
+    Generic(xcb::Error),
+    Conn(xcb::ConnError),
+}
+
+impl VidmodeError {
+    fn generic(e: xcb::Error) -> Box<dyn Error> {
+        Box::new(VidmodeError::Generic(e)) as Box<dyn Error>
+    }
+
+    fn conn(e: xcb::ConnError) -> Box<dyn Error> {
+        Box::new(VidmodeError::Conn(e)) as Box<dyn Error>
+    }
+}
+
+impl fmt::Display for VidmodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::VidmodeError::*;
+        // The wrapped xcb errors are reported through source()
+        match *self {
+            Generic(..) => write!(f, "vidmode request failed"),
+            Conn(..) => write!(f, "failed to connect to the X server"),
+        }
+    }
+}
+
+impl Error for VidmodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            VidmodeError::Generic(ref e) => Some(e),
+            VidmodeError::Conn(ref c) => Some(c),
+        }
+    }
+}
+
+/// Whether the error is a failure to connect to the X server
+pub(super) fn is_connection_error(e: &(dyn Error + 'static)) -> bool {
+    matches!(
+        e.downcast_ref::<VidmodeError>(),
+        Some(VidmodeError::Conn(..))
+    )
+}
+
+/// Whether the X server did not accept the connection (yet)
+pub(super) fn is_server_not_ready(e: &(dyn Error + 'static)) -> bool {
+    matches!(
+        e.downcast_ref::<VidmodeError>(),
+        Some(VidmodeError::Conn(xcb::ConnError::Connection))
+    )
+}
+
+/// Wrapping struct for VidMode state
+pub struct VidmodeState {
+    conn: xcb::Connection,
+    screen_num: u16,
+
+    /// The ramp size.
+    ramp_size: u16,
+
+    /// The initial gamma ramp values - used for restore
+    saved_ramps: (Vec<u16>, Vec<u16>, Vec<u16>),
+
+    /// A scratchpad for color computation - it saves the cost of
+    /// allocating three new arrays whenever set_temperature() is
+    /// called.
+    scratch: (Vec<u16>, Vec<u16>, Vec<u16>),
+
+    /// How the gamma ramps are computed
+    fill_options: FillOptions,
+}
+
+impl VidmodeState {
+    fn init(options: &GammaOptions) -> Result<VidmodeState> {
+        let (conn, screen_num) = xcb::Connection::connect(None).map_err(VidmodeError::conn)?;
+        let screen_num = options.screen.unwrap_or(screen_num as usize);
+
+        // Make sure the extension is present before using it
+        let cookie = conn.send_request(&xf86vidmode::QueryVersion {});
+        conn.wait_for_reply(cookie).map_err(VidmodeError::generic)?;
+
+        Ok(VidmodeState {
+            conn,
+            screen_num: screen_num as u16,
+            ramp_size: 0,
+            saved_ramps: (vec![], vec![], vec![]),
+            scratch: (vec![], vec![], vec![]),
+            fill_options: options.fill.clone(),
+        })
+    }
+}
+
+impl GammaMethod for VidmodeState {
+    fn name(&self) -> &'static str {
+        "vidmode"
+    }
+
+    //
+    // Restore saved gamma ramps
+    //
+    fn restore(&self) -> Result<()> {
+        self.conn.send_request(&xf86vidmode::SetGammaRamp {
+            screen: self.screen_num,
+            size: self.ramp_size,
+            red: &self.saved_ramps.0[..],
+            green: &self.saved_ramps.1[..],
+            blue: &self.saved_ramps.2[..],
+        });
+
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn adjusted_outputs(&self) -> Vec<OutputInfo> {
+        vec![OutputInfo {
+            id: self.screen_num as u32,
+            ramp_size: self.ramp_size as u32,
+        }]
+    }
+
+    fn saved_ramps(&self) -> Vec<OutputRamps> {
+        let (ref red, ref green, ref blue) = self.saved_ramps;
+        vec![OutputRamps {
+            output: OutputInfo {
+                id: self.screen_num as u32,
+                ramp_size: self.ramp_size as u32,
+            },
+            red: red.clone(),
+            green: green.clone(),
+            blue: blue.clone(),
+        }]
+    }
+
+    fn set_temperature(&mut self, setting: &transition::ColorSetting) -> Result<()> {
+        let (ref mut r, ref mut g, ref mut b) = self.scratch;
+
+        colorramp::fill_identity(r, g, b);
+
+        // Compute new gamma ramps
+        colorramp::fill(
+            &mut r[..],
+            &mut g[..],
+            &mut b[..],
+            setting,
+            self.ramp_size as usize,
+            &self.fill_options,
+        );
+
+        // Set the gamma ramp
+        self.conn.send_request(&xf86vidmode::SetGammaRamp {
+            screen: self.screen_num,
+            size: self.ramp_size,
+            red: &r[..],
+            green: &g[..],
+            blue: &b[..],
+        });
+
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Save the ramp size and initial gamma ramps of the screen
+    fn start(&mut self) -> Result<()> {
+        let req = self.conn.send_request(&xf86vidmode::GetGammaRampSize {
+            screen: self.screen_num,
+        });
+
+        let reply = self
+            .conn
+            .wait_for_reply(req)
+            .map_err(VidmodeError::generic)?;
+
+        self.ramp_size = reply.size();
+        verbose!(
+            "Screen {}: gamma ramp size {}",
+            self.screen_num,
+            self.ramp_size
+        );
+
+        let req = self.conn.send_request(&xf86vidmode::GetGammaRamp {
+            screen: self.screen_num,
+            size: self.ramp_size,
+        });
+
+        let reply = self
+            .conn
+            .wait_for_reply(req)
+            .map_err(VidmodeError::generic)?;
+
+        let red = reply.red().to_vec();
+        let green = reply.green().to_vec();
+        let blue = reply.blue().to_vec();
+
+        self.saved_ramps = (red.clone(), green.clone(), blue.clone());
+        self.scratch = (red, green, blue);
+        Ok(())
+    }
+}
+
+/// The init function
+pub fn init(options: &GammaOptions) -> Result<Box<dyn GammaMethod>> {
+    VidmodeState::init(options).map(|v| Box::new(v) as Box<dyn GammaMethod>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_expose_their_source() {
+        let e = VidmodeError::Conn(xcb::ConnError::Connection);
+        assert!(e.source().is_some());
+        assert_eq!(e.to_string(), "failed to connect to the X server");
+        assert!(is_server_not_ready(&e));
+        assert!(!is_server_not_ready(&VidmodeError::Conn(
+            xcb::ConnError::ClosedParseErr
+        )));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::colorramp::{self, FillOptions, RampDepth};
+use crate::transition;
+
+use super::GammaMethod;
+use super::GammaOptions;
+use super::OutputInfo;
+use super::OutputRamps;
+use super::Result;
+use std::error::Error;
+use std::ffi::c_void;
+use std::fmt;
+use std::io;
+use std::ptr;
+
+type Hdc = *mut c_void;
+type Hwnd = *mut c_void;
+
+/// GDI gamma ramps always have 256 entries per channel
+const GAMMA_RAMP_SIZE: usize = 256;
+
+type GammaRamp = [[u16; GAMMA_RAMP_SIZE]; 3];
+
+#[link(name = "user32")]
+extern "system" {
+    fn GetDC(hwnd: Hwnd) -> Hdc;
+    fn ReleaseDC(hwnd: Hwnd, hdc: Hdc) -> i32;
+}
+
+#[link(name = "gdi32")]
+extern "system" {
+    fn GetDeviceGammaRamp(hdc: Hdc, ramp: *mut c_void) -> i32;
+    fn SetDeviceGammaRamp(hdc: Hdc, ramp: *mut c_void) -> i32;
+}
+
+/// Wrapper for Win32 GDI errors
+#[derive(Debug)]
+pub enum W32gdiError {
+    /// A GDI call failed with the given OS error
+    Gdi(&'static str, io::Error),
+}
+
+impl W32gdiError {
+    fn last(call: &'static str) -> Box<dyn Error> {
+        Box::new(W32gdiError::Gdi(call, io::Error::last_os_error())) as Box<dyn Error>
+    }
+}
+
+impl fmt::Display for W32gdiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::W32gdiError::*;
+        // The wrapped OS error is reported through source()
+        match *self {
+            Gdi(call, _) => write!(f, "w32gdi error: {call} failed"),
+        }
+    }
+}
+
+impl Error for W32gdiError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            W32gdiError::Gdi(_, ref e) => Some(e),
+        }
+    }
+}
+
+/// The device context of the primary display, released on drop
+struct ScreenDc(Hdc);
+
+impl ScreenDc {
+    fn get() -> Result<ScreenDc> {
+        let hdc = unsafe { GetDC(ptr::null_mut()) };
+        if hdc.is_null() {
+            return Err(W32gdiError::last("GetDC"));
+        }
+        Ok(ScreenDc(hdc))
+    }
+
+    fn set_ramp(&self, ramp: &mut GammaRamp) -> Result<()> {
+        if unsafe { SetDeviceGammaRamp(self.0, ramp.as_mut_ptr() as *mut c_void) } == 0 {
+            return Err(W32gdiError::last("SetDeviceGammaRamp"));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ScreenDc {
+    fn drop(&mut self) {
+        unsafe { ReleaseDC(ptr::null_mut(), self.0) };
+    }
+}
+
+/// Wrapping struct for W32GDI state
+pub struct W32gdiState {
+    /// The initial gamma ramp - used for restore
+    saved_ramp: GammaRamp,
+
+    /// A scratchpad for color computation
+    scratch: GammaRamp,
+
+    /// How the gamma ramps are computed
+    fill_options: FillOptions,
+}
+
+impl W32gdiState {
+    fn init(options: &GammaOptions) -> Result<W32gdiState> {
+        // Make sure there is a display to adjust
+        ScreenDc::get()?;
+
+        Ok(W32gdiState {
+            saved_ramp: [[0; GAMMA_RAMP_SIZE]; 3],
+            scratch: [[0; GAMMA_RAMP_SIZE]; 3],
+            fill_options: options.fill.clone(),
+        })
+    }
+}
+
+impl GammaMethod for W32gdiState {
+    fn name(&self) -> &'static str {
+        "w32gdi"
+    }
+
+    //
+    // Restore saved gamma ramps
+    //
+    fn restore(&self) -> Result<()> {
+        let mut ramp = self.saved_ramp;
+        ScreenDc::get()?.set_ramp(&mut ramp)
+    }
+
+    /// GDI only knows about the primary display
+    fn adjusted_outputs(&self) -> Vec<OutputInfo> {
+        vec![OutputInfo {
+            id: 0,
+            ramp_size: GAMMA_RAMP_SIZE as u32,
+        }]
+    }
+
+    fn saved_ramps(&self) -> Vec<OutputRamps> {
+        let [ref red, ref green, ref blue] = self.saved_ramp;
+        vec![OutputRamps {
+            output: OutputInfo {
+                id: 0,
+                ramp_size: GAMMA_RAMP_SIZE as u32,
+            },
+            red: red.to_vec(),
+            green: green.to_vec(),
+            blue: blue.to_vec(),
+        }]
+    }
+
+    fn set_temperature(&mut self, setting: &transition::ColorSetting) -> Result<()> {
+        let [ref mut r, ref mut g, ref mut b] = self.scratch;
+
+        // Compute new gamma ramps
+        colorramp::fill_scaled(
+            &mut r[..],
+            &mut g[..],
+            &mut b[..],
+            setting,
+            RampDepth::FULL,
+            &self.fill_options,
+        );
+
+        // Set the gamma ramp
+        ScreenDc::get()?.set_ramp(&mut self.scratch)
+    }
+
+    /// Save the initial gamma ramp of the primary display
+    fn start(&mut self) -> Result<()> {
+        let dc = ScreenDc::get()?;
+        let ramp = self.saved_ramp.as_mut_ptr() as *mut c_void;
+        if unsafe { GetDeviceGammaRamp(dc.0, ramp) } == 0 {
+            return Err(W32gdiError::last("GetDeviceGammaRamp"));
+        }
+        Ok(())
+    }
+}
+
+/// The init function
+pub fn init(options: &GammaOptions) -> Result<Box<dyn GammaMethod>> {
+    W32gdiState::init(options).map(|w| Box::new(w) as Box<dyn GammaMethod>)
+}
@@ -0,0 +1,348 @@
+use crate::colorramp::{self, FillOptions};
+use crate::transition;
+use wayland_client::protocol::{wl_output, wl_registry};
+use wayland_client::{Connection, Dispatch, EventQueue, QueueHandle};
+use wayland_protocols_wlr::gamma_control::v1::client::{
+    zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1,
+    zwlr_gamma_control_v1::{self, ZwlrGammaControlV1},
+};
+
+use super::GammaMethod;
+use super::GammaOptions;
+use super::OutputInfo;
+use super::Result;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::unix::io::{AsFd, FromRawFd};
+
+/// Wrapper for Wayland errors
+#[derive(Debug)]
+pub enum WaylandError {
+    Connect(wayland_client::ConnectError),
+    Dispatch(wayland_client::DispatchError),
+    Backend(wayland_client::backend::WaylandError),
+    Io(io::Error),
+    NoGammaControl,
+}
+
+impl WaylandError {
+    fn connect(e: wayland_client::ConnectError) -> Box<dyn Error> {
+        Box::new(WaylandError::Connect(e)) as Box<dyn Error>
+    }
+
+    fn dispatch(e: wayland_client::DispatchError) -> Box<dyn Error> {
+        Box::new(WaylandError::Dispatch(e)) as Box<dyn Error>
+    }
+
+    fn backend(e: wayland_client::backend::WaylandError) -> Box<dyn Error> {
+        Box::new(WaylandError::Backend(e)) as Box<dyn Error>
+    }
+
+    fn io(e: io::Error) -> Box<dyn Error> {
+        Box::new(WaylandError::Io(e)) as Box<dyn Error>
+    }
+
+    fn no_gamma_control() -> Box<dyn Error> {
+        Box::new(WaylandError::NoGammaControl) as Box<dyn Error>
+    }
+}
+
+impl fmt::Display for WaylandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::WaylandError::*;
+        // The wrapped wayland and io errors are reported through source()
+        match *self {
+            Connect(..) => write!(f, "failed to connect to the wayland compositor"),
+            Dispatch(..) => write!(f, "wayland request failed"),
+            Backend(..) => write!(f, "wayland connection failed"),
+            Io(..) => write!(f, "failed to write gamma ramps"),
+            NoGammaControl => write!(
+                f,
+                "compositor does not support wlr-gamma-control-unstable-v1"
+            ),
+        }
+    }
+}
+
+impl Error for WaylandError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            WaylandError::Connect(ref e) => Some(e),
+            WaylandError::Dispatch(ref e) => Some(e),
+            WaylandError::Backend(ref e) => Some(e),
+            WaylandError::Io(ref e) => Some(e),
+            WaylandError::NoGammaControl => None,
+        }
+    }
+}
+
+/// Whether the error is a failure to connect to the compositor
+pub(super) fn is_connection_error(e: &(dyn Error + 'static)) -> bool {
+    matches!(
+        e.downcast_ref::<WaylandError>(),
+        Some(WaylandError::Connect(..))
+    )
+}
+
+struct Output {
+    output: wl_output::WlOutput,
+
+    /// The gamma control of the output, acquired in start()
+    control: Option<ZwlrGammaControlV1>,
+
+    /// The ramp size, as announced by the compositor.
+    ramp_size: u32,
+
+    /// Set when the compositor refused or revoked the gamma control
+    failed: bool,
+
+    /// A scratchpad for color computation - it saves the cost of
+    /// allocating three new arrays whenever set_temperature() is
+    /// called.
+    scratch: (Vec<u16>, Vec<u16>, Vec<u16>),
+}
+
+/// The part of the state the event handlers operate on
+struct WaylandData {
+    manager: Option<ZwlrGammaControlManagerV1>,
+    outputs: Vec<Output>,
+}
+
+/// Wrapping struct for Wayland state
+pub struct WaylandState {
+    conn: Connection,
+    queue: EventQueue<WaylandData>,
+    data: WaylandData,
+
+    /// How the gamma ramps are computed
+    fill_options: FillOptions,
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for WaylandData {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name, interface, ..
+        } = event
+        {
+            match &interface[..] {
+                "zwlr_gamma_control_manager_v1" => {
+                    state.manager = Some(registry.bind(name, 1, qh, ()));
+                }
+                "wl_output" => state.outputs.push(Output {
+                    output: registry.bind(name, 1, qh, ()),
+                    control: None,
+                    ramp_size: 0,
+                    failed: false,
+                    scratch: (vec![], vec![], vec![]),
+                }),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, ()> for WaylandData {
+    fn event(
+        _: &mut Self,
+        _: &wl_output::WlOutput,
+        _: wl_output::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrGammaControlManagerV1, ()> for WaylandData {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrGammaControlManagerV1,
+        _: <ZwlrGammaControlManagerV1 as wayland_client::Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+/// The user data is the index of the output the control belongs to
+impl Dispatch<ZwlrGammaControlV1, usize> for WaylandData {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrGammaControlV1,
+        event: zwlr_gamma_control_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = &mut state.outputs[*index];
+        match event {
+            zwlr_gamma_control_v1::Event::GammaSize { size } => {
+                let size = size as usize;
+                output.ramp_size = size as u32;
+                output.scratch = (vec![0; size], vec![0; size], vec![0; size]);
+            }
+            zwlr_gamma_control_v1::Event::Failed => output.failed = true,
+            _ => {}
+        }
+    }
+}
+
+/// Send the given ramps to the compositor
+///
+/// The protocol expects the red, green and blue ramps back to back
+/// in a file descriptor.
+fn set_gamma(control: &ZwlrGammaControlV1, r: &[u16], g: &[u16], b: &[u16]) -> Result<()> {
+    let fd = unsafe { libc::memfd_create(c"redshift-gamma".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd == -1 {
+        return Err(WaylandError::io(io::Error::last_os_error()));
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+
+    let bytes = r
+        .iter()
+        .chain(g)
+        .chain(b)
+        .flat_map(|v| v.to_ne_bytes())
+        .collect::<Vec<u8>>();
+    file.write_all(&bytes).map_err(WaylandError::io)?;
+    file.seek(SeekFrom::Start(0)).map_err(WaylandError::io)?;
+
+    control.set_gamma(file.as_fd());
+    Ok(())
+}
+
+impl WaylandState {
+    fn init(options: &GammaOptions) -> Result<WaylandState> {
+        let conn = Connection::connect_to_env().map_err(WaylandError::connect)?;
+        let mut queue = conn.new_event_queue();
+        let qh = queue.handle();
+        conn.display().get_registry(&qh, ());
+
+        let mut data = WaylandData {
+            manager: None,
+            outputs: vec![],
+        };
+        queue.roundtrip(&mut data).map_err(WaylandError::dispatch)?;
+
+        if data.manager.is_none() {
+            return Err(WaylandError::no_gamma_control());
+        }
+
+        Ok(WaylandState {
+            conn,
+            queue,
+            data,
+            fill_options: options.fill.clone(),
+        })
+    }
+}
+
+impl GammaMethod for WaylandState {
+    fn name(&self) -> &'static str {
+        "wayland"
+    }
+
+    //
+    // Re-send neutral ramps
+    //
+    fn restore(&self) -> Result<()> {
+        for output in self.data.outputs.iter() {
+            if let (Some(control), false) = (&output.control, output.failed) {
+                let size = output.ramp_size as usize;
+                let (mut r, mut g, mut b) = (vec![0; size], vec![0; size], vec![0; size]);
+                colorramp::fill_identity(&mut r, &mut g, &mut b);
+
+                set_gamma(control, &r, &g, &b)?;
+            }
+        }
+        self.conn.flush().map_err(WaylandError::backend)?;
+        Ok(())
+    }
+
+    /// The ids are the indices of the outputs, in announcement order
+    fn adjusted_outputs(&self) -> Vec<OutputInfo> {
+        self.data
+            .outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| output.control.is_some() && !output.failed)
+            .map(|(i, output)| OutputInfo {
+                id: i as u32,
+                ramp_size: output.ramp_size,
+            })
+            .collect()
+    }
+
+    fn set_temperature(&mut self, setting: &transition::ColorSetting) -> Result<()> {
+        for output in self.data.outputs.iter_mut() {
+            let control = match (&output.control, output.failed) {
+                (Some(control), false) => control,
+                _ => continue,
+            };
+            let (ref mut r, ref mut g, ref mut b) = output.scratch;
+
+            colorramp::fill_identity(r, g, b);
+
+            // Compute new gamma ramps
+            colorramp::fill(
+                &mut r[..],
+                &mut g[..],
+                &mut b[..],
+                setting,
+                output.ramp_size as usize,
+                &self.fill_options,
+            );
+
+            // Set the gamma ramp
+            set_gamma(control, r, g, b)?;
+        }
+
+        self.queue
+            .roundtrip(&mut self.data)
+            .map_err(WaylandError::dispatch)?;
+        Ok(())
+    }
+
+    /// Acquire a gamma control and its ramp size for every output
+    fn start(&mut self) -> Result<()> {
+        let qh = self.queue.handle();
+        let manager = self
+            .data
+            .manager
+            .as_ref()
+            .ok_or_else(WaylandError::no_gamma_control)?;
+
+        for (i, output) in self.data.outputs.iter_mut().enumerate() {
+            output.control = Some(manager.get_gamma_control(&output.output, &qh, i));
+        }
+
+        self.queue
+            .roundtrip(&mut self.data)
+            .map_err(WaylandError::dispatch)?;
+
+        verbose!("Found {} outputs", self.data.outputs.len());
+        for (i, output) in self.data.outputs.iter().enumerate() {
+            if output.failed {
+                verbose!("Output {i}: gamma control refused by compositor");
+            } else {
+                verbose!("Output {i}: gamma ramp size {}", output.ramp_size);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The init function
+pub fn init(options: &GammaOptions) -> Result<Box<dyn GammaMethod>> {
+    WaylandState::init(options).map(|w| Box::new(w) as Box<dyn GammaMethod>)
+}
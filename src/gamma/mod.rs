@@ -1,13 +1,30 @@
+#[cfg(feature = "drm")]
+mod gamma_drm;
+#[cfg(all(feature = "quartz", target_os = "macos"))]
+mod gamma_quartz;
 #[cfg(feature = "randr")]
 mod gamma_randr;
+#[cfg(feature = "vidmode")]
+mod gamma_vidmode;
+#[cfg(all(feature = "w32gdi", windows))]
+mod gamma_w32gdi;
+#[cfg(feature = "wayland")]
+mod gamma_wayland;
 
 use super::Result;
+use crate::colorramp::{self, FillOptions, RampDepth};
 use crate::transition;
 
+#[cfg(any(test, feature = "testing"))]
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::time::Duration;
+use tokio::sync::mpsc;
 
-type GammaInit = fn() -> Result<Box<dyn GammaMethod>>;
+type GammaInit = fn(&GammaOptions) -> Result<Box<dyn GammaMethod>>;
 
 lazy_static! {
     static ref SUPPORTED_GAMMA_METHODS: HashMap<&'static str, GammaInit> = {
@@ -16,14 +33,247 @@ lazy_static! {
         #[cfg(feature = "randr")]
         m.insert("randr", gamma_randr::init);
 
+        #[cfg(feature = "vidmode")]
+        m.insert("vidmode", gamma_vidmode::init);
+
+        #[cfg(feature = "drm")]
+        m.insert("drm", gamma_drm::init);
+
+        #[cfg(feature = "wayland")]
+        m.insert("wayland", gamma_wayland::init);
+
+        #[cfg(all(feature = "quartz", target_os = "macos"))]
+        m.insert("quartz", gamma_quartz::init);
+
+        #[cfg(all(feature = "w32gdi", windows))]
+        m.insert("w32gdi", gamma_w32gdi::init);
+
         m.insert("dummy", init_dummy);
         m
     };
 }
 
+/// Order in which methods are tried when none is requested
+///
+/// Display server protocols come before direct hardware access, so that
+/// a session's own compositor wins over DRM. The dummy comes last.
+const METHOD_PRIORITY: [&str; 7] = [
+    "wayland", "randr", "drm", "vidmode", "quartz", "w32gdi", "dummy",
+];
+
+/// Methods going through an X server. In a Wayland session that is
+/// Xwayland, whose gamma ramps the compositor ignores.
+const X_METHODS: [&str; 2] = ["randr", "vidmode"];
+
+/// Number of times to retry connecting to a display server that is not
+/// ready yet, by default
+pub const CONNECT_RETRIES: usize = 3;
+
+/// Time to wait before the first retry to connect to the X server, it
+/// doubles with every further one
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Longest time to wait between two attempts to connect
+const MAX_CONNECT_RETRY_DELAY: Duration = Duration::from_secs(8);
+
+/// Errors selecting the gamma method
+#[derive(Debug)]
+pub enum MethodError {
+    /// The requested method does not exist in this build
+    Unknown(String),
+
+    /// No method could be initialised
+    NoneAvailable,
+}
+
+impl MethodError {
+    fn unknown(name: &str) -> Box<dyn Error> {
+        Box::new(MethodError::Unknown(name.to_string())) as Box<dyn Error>
+    }
+
+    fn none_available() -> Box<dyn Error> {
+        Box::new(MethodError::NoneAvailable) as Box<dyn Error>
+    }
+}
+
+impl fmt::Display for MethodError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::MethodError::*;
+        match *self {
+            Unknown(ref m) => write!(
+                f,
+                "unknown method '{m}', available: {}",
+                method_names().join(", ")
+            ),
+            NoneAvailable => write!(f, "No gamma adjustment method available"),
+        }
+    }
+}
+
+impl Error for MethodError {}
+
+/// Whether the error is a failure to connect to the display server, as
+/// opposed to the server not supporting a method
+///
+/// Such failures can go away by themselves, eg while the X server is
+/// still starting up.
+pub fn is_connection_error(e: &(dyn Error + 'static)) -> bool {
+    CONNECTION_ERRORS
+        .iter()
+        .any(|is_connection_error| is_connection_error(e))
+}
+
+/// The checks of the methods that connect to a display server
+const CONNECTION_ERRORS: &[fn(&(dyn Error + 'static)) -> bool] = &[
+    #[cfg(feature = "randr")]
+    gamma_randr::is_connection_error,
+    #[cfg(feature = "vidmode")]
+    gamma_vidmode::is_connection_error,
+    #[cfg(feature = "wayland")]
+    gamma_wayland::is_connection_error,
+];
+
+/// Whether the error is the X server not accepting connections (yet),
+/// which unlike eg a missing `$DISPLAY` is worth retrying
+fn is_x_server_not_ready(e: &(dyn Error + 'static)) -> bool {
+    X_SERVER_NOT_READY
+        .iter()
+        .any(|is_not_ready| is_not_ready(e))
+}
+
+/// The checks of the methods that connect to an X server
+const X_SERVER_NOT_READY: &[fn(&(dyn Error + 'static)) -> bool] = &[
+    #[cfg(feature = "randr")]
+    gamma_randr::is_server_not_ready,
+    #[cfg(feature = "vidmode")]
+    gamma_vidmode::is_server_not_ready,
+];
+
+/// Time to wait before the given retry to connect, counting from 1
+fn connect_retry_delay(retry: usize) -> Duration {
+    let factor = 1u32 << (retry.max(1) - 1).min(16);
+    CONNECT_RETRY_DELAY
+        .saturating_mul(factor)
+        .min(MAX_CONNECT_RETRY_DELAY)
+}
+
+/// Options for selecting what a gamma method should adjust
+///
+/// Methods only look at the options that make sense for them and
+/// ignore the rest.
+#[derive(Debug, Clone)]
+pub struct GammaOptions {
+    /// The DRM card to use (ie `/dev/dri/card<N>`)
+    pub card: usize,
+
+    /// The X screen to use, instead of the default one of the display
+    pub screen: Option<usize>,
+
+    /// Adjust every X screen rather than only `screen`. The CRTC
+    /// indices then count through the screens one after the other.
+    pub all_screens: bool,
+
+    /// Only adjust the CRTCs with these indices, all of them if empty
+    pub crtcs: Vec<usize>,
+
+    /// Temperatures to use instead of the one of the color setting, by
+    /// CRTC index. These CRTCs are adjusted even when not in `crtcs`.
+    pub crtc_temps: HashMap<usize, i32>,
+
+    /// Also adjust the CRTCs driving the outputs with these names (eg
+    /// `HDMI-1`)
+    pub outputs: Vec<String>,
+
+    /// Compute the gamma ramps but do not send them to the display
+    pub dry_run: bool,
+
+    /// When the requested method fails, try the other methods and
+    /// finally the dummy instead of giving up (see `start_gamma_method`)
+    pub fallback: bool,
+
+    /// How many times to retry connecting to the X server when it is
+    /// not accepting connections yet
+    pub connect_retries: usize,
+
+    /// Only warn when the server's RandR version is older than needed,
+    /// instead of failing
+    pub skip_version_check: bool,
+
+    /// Keep stdout clean for machine readable output - the dummy method
+    /// does not print what it would set
+    pub quiet: bool,
+
+    /// Have the dummy method compute gamma ramps of this size and print
+    /// a summary of them, to check the color math without a display
+    pub dummy_ramp_size: Option<usize>,
+
+    /// How the gamma ramps are computed from the color settings
+    pub fill: FillOptions,
+}
+
+impl Default for GammaOptions {
+    fn default() -> GammaOptions {
+        GammaOptions {
+            card: 0,
+            screen: None,
+            all_screens: false,
+            crtcs: vec![],
+            crtc_temps: HashMap::new(),
+            outputs: vec![],
+            dry_run: false,
+            fallback: false,
+            connect_retries: CONNECT_RETRIES,
+            skip_version_check: false,
+            quiet: false,
+            dummy_ramp_size: None,
+            fill: FillOptions::default(),
+        }
+    }
+}
+
+/// An output whose gamma ramps a method adjusts
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputInfo {
+    /// The method specific id of the output (CRTC id, screen number...)
+    pub id: u32,
+
+    /// The number of entries in each of the output's gamma ramps
+    pub ramp_size: u32,
+}
+
+/// The area of the screen an output shows, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    /// Whether the point is within the area
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let (left, top) = (self.x as i32, self.y as i32);
+        (left..left + self.width as i32).contains(&x)
+            && (top..top + self.height as i32).contains(&y)
+    }
+}
+
+/// The gamma ramps of an output, one entry per channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputRamps {
+    pub output: OutputInfo,
+    pub red: Vec<u16>,
+    pub green: Vec<u16>,
+    pub blue: Vec<u16>,
+}
+
 /// Any gamma method provider should implement this trait
 ///
 pub trait GammaMethod {
+    /// The name the method is selected by (see `method_names`)
+    fn name(&self) -> &'static str;
+
     /// Initialization method
     ///
     /// Called before set_temperature()
@@ -32,65 +282,733 @@ pub trait GammaMethod {
     /// Use the given color setting to adjust the screen temperature
     ///
     /// When running continually, this method is invoked
-    /// repeatedly. In oneshot mode, this method is invoked once.
+    /// repeatedly. In oneshot mode, this method is invoked once
+    /// (see `apply_once`).
     fn set_temperature(&mut self, setting: &transition::ColorSetting) -> Result<()>;
 
     /// The restore method is called when Redshift exits from
     /// running in continual mode.
     fn restore(&self) -> Result<()>;
+
+    /// The outputs that set_temperature() adjusts
+    ///
+    /// Only meaningful after start() - before that no outputs are
+    /// known yet.
+    fn adjusted_outputs(&self) -> Vec<OutputInfo>;
+
+    /// The gamma ramps the outputs had when start() was called
+    ///
+    /// Methods that cannot read the ramps back return none.
+    fn saved_ramps(&self) -> Vec<OutputRamps> {
+        vec![]
+    }
+
+    /// Whether the gamma ramps of the outputs no longer are the ones
+    /// set_temperature() last set, eg because the driver reset them
+    ///
+    /// Methods that cannot read the ramps back never see a change.
+    fn ramps_changed(&self) -> Result<bool> {
+        Ok(false)
+    }
 }
 
-fn init_dummy() -> Result<Box<dyn GammaMethod>> {
-    Ok(Box::new(DummyMethod) as Box<dyn GammaMethod>)
+fn init_dummy(options: &GammaOptions) -> Result<Box<dyn GammaMethod>> {
+    Ok(Box::new(DummyMethod {
+        quiet: options.quiet,
+        fill_options: options.fill.clone(),
+        scratch: options
+            .dummy_ramp_size
+            .map(|size| (vec![0; size], vec![0; size], vec![0; size])),
+    }) as Box<dyn GammaMethod>)
+}
+
+/// Names of all gamma methods compiled into this build, sorted
+pub fn method_names() -> Vec<&'static str> {
+    let mut names = SUPPORTED_GAMMA_METHODS.keys().copied().collect::<Vec<_>>();
+    names.sort_unstable();
+    names
+}
+
+/// Names of the gamma methods compiled into this build, in the order
+/// auto-detection tries them (the dummy being last)
+pub fn method_priority() -> Vec<&'static str> {
+    METHOD_PRIORITY
+        .iter()
+        .copied()
+        .filter(|name| SUPPORTED_GAMMA_METHODS.contains_key(name))
+        .collect()
+}
+
+/// Whether the variables, as given by `var`, are the ones of a Wayland
+/// session
+fn is_wayland_session(var: impl Fn(&str) -> Option<String>) -> bool {
+    var("XDG_SESSION_TYPE").is_some_and(|t| t == "wayland")
+        || var("WAYLAND_DISPLAY").is_some_and(|d| !d.is_empty())
+}
+
+fn wayland_session() -> bool {
+    is_wayland_session(|name| std::env::var(name).ok())
+}
+
+/// The `method_priority` order, with the X methods moved right before
+/// the dummy in a Wayland session
+fn detection_order(wayland_session: bool) -> Vec<&'static str> {
+    let mut order = method_priority();
+    if wayland_session {
+        // The sort is stable and the dummy stays last
+        order.sort_by_key(|name| match *name {
+            "dummy" => 2,
+            name if X_METHODS.contains(&name) => 1,
+            _ => 0,
+        });
+    }
+    order
+}
+
+/// Warn when the method will likely only adjust Xwayland
+fn warn_xwayland(name: &str) {
+    if X_METHODS.contains(&name) && wayland_session() {
+        let hint = if SUPPORTED_GAMMA_METHODS.contains_key("wayland") {
+            "use --method wayland"
+        } else {
+            "build with the wayland feature and use --method wayland"
+        };
+        eprintln!(
+            "WARNING: {name} only adjusts Xwayland in a Wayland session, which the compositor may ignore ({hint})"
+        );
+    }
 }
 
 /// Initialise the gamma adjustment method
 ///
 /// If a specific method is requsted (ie method_name is `Some(..)`)
-/// then it is assumed that the method exists and we can call its
-/// initialisation function. If a requested method does not exist,
-/// this function panics.
+/// then its initialisation function is called. If a requested method
+/// does not exist, an error listing the available methods is returned.
 ///
 /// If `method_name` is `None` then all available methods (except for
-/// the dummy) are tried in turn until one successfully starts - and
-/// then that method is used.
-pub fn init_gamma_method(method_name: Option<&str>) -> Result<Box<dyn GammaMethod>> {
+/// the dummy) are tried in turn, in the `method_priority` order, until
+/// one successfully starts - and then that method is used. In a
+/// Wayland session the X methods are tried last.
+///
+/// Connecting to the X server is only tried once, `start_gamma_method`
+/// retries it.
+pub fn init_gamma_method(
+    method_name: Option<&str>,
+    options: &GammaOptions,
+) -> Result<Box<dyn GammaMethod>> {
+    init_from(
+        &SUPPORTED_GAMMA_METHODS,
+        &detection_order(wayland_session()),
+        method_name,
+        options,
+    )
+}
+
+/// `init_gamma_method` with the given methods, tried in `order` when
+/// none is requested
+fn init_from(
+    methods: &HashMap<&'static str, GammaInit>,
+    order: &[&'static str],
+    method_name: Option<&str>,
+    options: &GammaOptions,
+) -> Result<Box<dyn GammaMethod>> {
     match method_name {
-        Some(m) => SUPPORTED_GAMMA_METHODS[m](),
+        Some(m) => match methods.get(m) {
+            Some(method_init) => {
+                warn_xwayland(m);
+                method_init(options)
+            }
+            None => Err(MethodError::unknown(m)),
+        },
         None => {
             // Loop over each method and try their init function
             // (skipping the dummy)
-            SUPPORTED_GAMMA_METHODS
+            order
                 .iter()
-                .filter_map(|(name, method_init)| {
-                    if &name[..] == "dummy" {
+                .filter_map(|&name| {
+                    if name == "dummy" {
                         None
                     } else {
-                        method_init().ok()
+                        match methods[name](options) {
+                            Ok(method) => {
+                                warn_xwayland(name);
+                                Some(method)
+                            }
+                            Err(e) => {
+                                verbose!("Method {name} is not available: {e}");
+                                None
+                            }
+                        }
                     }
                 })
                 .take(1)
                 .next()
-                .ok_or_else(|| Box::<dyn Error>::from("No gamma adjustment method available"))
+                .ok_or_else(MethodError::none_available)
+        }
+    }
+}
+
+/// Initialise and start the gamma adjustment method
+///
+/// Without `options.fallback` this is `init_gamma_method` followed by
+/// `start()`. With it, a requested method that fails to initialise or
+/// start is skipped with a warning, and the other methods are tried in
+/// turn - falling back to the dummy when none of them works.
+///
+/// An X server that does not accept connections yet is retried up to
+/// `options.connect_retries` times, waiting longer every time.
+pub async fn start_gamma_method(
+    method_name: Option<&str>,
+    options: &GammaOptions,
+) -> Result<Box<dyn GammaMethod>> {
+    start_from(
+        &SUPPORTED_GAMMA_METHODS,
+        &detection_order(wayland_session()),
+        method_name,
+        options,
+    )
+    .await
+}
+
+/// `start_gamma_method` with the given methods, tried in `order`
+async fn start_from(
+    methods: &HashMap<&'static str, GammaInit>,
+    order: &[&'static str],
+    method_name: Option<&str>,
+    options: &GammaOptions,
+) -> Result<Box<dyn GammaMethod>> {
+    if !options.fallback {
+        let mut method = match method_name {
+            Some(m) => init_with_retries(methods, m, options).await?,
+            None => detect(methods, order, options).await?,
+        };
+        method.start()?;
+        return Ok(method);
+    }
+
+    if let Some(m) = method_name {
+        match start_named(methods, m, options).await {
+            Ok(method) => return Ok(method),
+            Err(e) => eprintln!("WARNING: method {m} failed, trying the others: {e}"),
+        }
+    }
+
+    for &name in order {
+        if name == "dummy" || Some(name) == method_name {
+            continue;
+        }
+        match start_named(methods, name, options).await {
+            Ok(method) => return Ok(method),
+            Err(e) => verbose!("Method {name} is not available: {e}"),
         }
     }
+
+    eprintln!("WARNING: no gamma adjustment method works, using the dummy");
+    start_named(methods, "dummy", options).await
+}
+
+/// Initialise and start the named method
+async fn start_named(
+    methods: &HashMap<&'static str, GammaInit>,
+    name: &str,
+    options: &GammaOptions,
+) -> Result<Box<dyn GammaMethod>> {
+    let mut method = init_with_retries(methods, name, options).await?;
+    method.start()?;
+    Ok(method)
+}
+
+/// Initialise the first method of `order` that works, skipping the
+/// dummy, like `init_gamma_method` without a method name does
+async fn detect(
+    methods: &HashMap<&'static str, GammaInit>,
+    order: &[&'static str],
+    options: &GammaOptions,
+) -> Result<Box<dyn GammaMethod>> {
+    for &name in order {
+        if name == "dummy" {
+            continue;
+        }
+        match init_with_retries(methods, name, options).await {
+            Ok(method) => return Ok(method),
+            Err(e) => verbose!("Method {name} is not available: {e}"),
+        }
+    }
+    Err(MethodError::none_available())
+}
+
+/// Initialise the named method, retrying while the X server does not
+/// accept connections
+async fn init_with_retries(
+    methods: &HashMap<&'static str, GammaInit>,
+    name: &str,
+    options: &GammaOptions,
+) -> Result<Box<dyn GammaMethod>> {
+    let mut retry = 0;
+    loop {
+        match init_from(methods, &[], Some(name), options) {
+            Err(e) if retry < options.connect_retries && is_x_server_not_ready(&*e) => {
+                retry += 1;
+                verbose!(
+                    "X server not ready, retrying ({retry}/{})",
+                    options.connect_retries
+                );
+            }
+            result => return result,
+        }
+        tokio::time::sleep(connect_retry_delay(retry)).await;
+    }
+}
+
+/// Apply the color setting once with the given method
+///
+/// This initialises and starts the method (see `start_gamma_method`),
+/// sets the color setting and returns. The adjustment stays in effect
+/// after returning, nothing is restored.
+pub async fn apply_once(
+    method_name: Option<&str>,
+    options: &GammaOptions,
+    setting: &transition::ColorSetting,
+) -> Result<()> {
+    start_gamma_method(method_name, options)
+        .await?
+        .set_temperature(setting)
+}
+
+/// Apply the color setting and send it over `tx` once it is in effect
+///
+/// Nothing is sent when applying fails. A receiver that went away is not
+/// an error: the adjustment itself succeeded.
+pub async fn apply_with_notify(
+    method: &mut dyn GammaMethod,
+    setting: &transition::ColorSetting,
+    tx: &mpsc::Sender<transition::ColorSetting>,
+) -> Result<()> {
+    method.set_temperature(setting)?;
+    let _ = tx.send(*setting).await;
+    Ok(())
+}
+
+pub struct DummyMethod {
+    quiet: bool,
+
+    /// How the gamma ramps are computed
+    fill_options: FillOptions,
+
+    /// Ramps to compute on every set_temperature(), when a ramp size
+    /// was given
+    scratch: Option<(Vec<u16>, Vec<u16>, Vec<u16>)>,
 }
 
-pub struct DummyMethod;
 impl GammaMethod for DummyMethod {
+    fn name(&self) -> &'static str {
+        "dummy"
+    }
+
     fn restore(&self) -> Result<()> {
         Ok(())
     }
 
+    /// The simulated output, if there is one
+    fn adjusted_outputs(&self) -> Vec<OutputInfo> {
+        match self.scratch {
+            Some((ref r, _, _)) => vec![OutputInfo {
+                id: 0,
+                ramp_size: r.len() as u32,
+            }],
+            None => vec![],
+        }
+    }
+
     fn set_temperature(&mut self, setting: &transition::ColorSetting) -> Result<()> {
+        if let Some((ref mut r, ref mut g, ref mut b)) = self.scratch {
+            colorramp::fill_scaled(r, g, b, setting, RampDepth::FULL, &self.fill_options);
+        }
+        if self.quiet {
+            return Ok(());
+        }
         println!("Temperature: {}", setting.temp);
+        println!("Brightness: {:.2}", setting.brightness);
+        if truecolor_terminal() {
+            println!("Color: {}", swatch(setting, &self.fill_options));
+        }
+        if let Some((ref r, ref g, ref b)) = self.scratch {
+            print!("{}", ramp_summary(r, g, b));
+        }
         Ok(())
     }
 
     fn start(&mut self) -> Result<()> {
+        if self.quiet {
+            return Ok(());
+        }
         println!(
             "WARNING: Using dummy gamma method! Display will not affected by this gamma method."
         );
         Ok(())
     }
 }
+
+/// The size, first and last entries and a checksum of computed ramps
+///
+/// The checksum is the sum of every entry of the three ramps.
+fn ramp_summary(r: &[u16], g: &[u16], b: &[u16]) -> String {
+    let entry = |i: Option<usize>| match i {
+        Some(i) => format!("{},{},{}", r[i], g[i], b[i]),
+        None => "-".to_string(),
+    };
+    let checksum = r.iter().chain(g).chain(b).map(|&v| v as u64).sum::<u64>();
+    format!(
+        "Ramp size: {}\nRamp first: {}\nRamp last: {}\nRamp checksum: {checksum}\n",
+        r.len(),
+        entry((!r.is_empty()).then_some(0)),
+        entry(r.len().checked_sub(1)),
+    )
+}
+
+/// Whether stdout is a terminal that advertises 24-bit colors in
+/// `COLORTERM`
+fn truecolor_terminal() -> bool {
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    io::stdout().is_terminal() && (colorterm == "truecolor" || colorterm == "24bit")
+}
+
+/// A few blank cells with the white point of the setting as background,
+/// using ANSI truecolor escapes
+fn swatch(setting: &transition::ColorSetting, options: &FillOptions) -> String {
+    let white_point = options.white_point_of(setting);
+    let [r, g, b] = [0, 1, 2].map(|c| {
+        let c = white_point[c] * setting.brightness * setting.brightness_rgb[c];
+        (c * 255.0).round().clamp(0.0, 255.0) as u8
+    });
+    format!("\x1b[48;2;{r};{g};{b}m      \x1b[0m")
+}
+
+/// A gamma method that only records what it is asked to do
+///
+/// It does not touch any display, which makes it useful to test what
+/// would be applied. It is not registered as a selectable method, and
+/// only built for tests or with the `testing` feature.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Default, Debug)]
+pub struct RecordingMethod {
+    /// Whether start() was called
+    pub started: bool,
+
+    /// Every color setting passed to set_temperature(), in order
+    pub settings: Vec<transition::ColorSetting>,
+
+    /// Number of restore() calls
+    pub restores: Cell<usize>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl GammaMethod for RecordingMethod {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    fn restore(&self) -> Result<()> {
+        self.restores.set(self.restores.get() + 1);
+        Ok(())
+    }
+
+    fn adjusted_outputs(&self) -> Vec<OutputInfo> {
+        vec![]
+    }
+
+    fn set_temperature(&mut self, setting: &transition::ColorSetting) -> Result<()> {
+        self.settings.push(*setting);
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<()> {
+        self.started = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_method_is_an_error() {
+        match init_gamma_method(Some("bogus"), &GammaOptions::default()) {
+            Ok(_) => panic!("bogus method should not initialise"),
+            Err(e) => assert!(e.to_string().starts_with("unknown method 'bogus'")),
+        }
+    }
+
+    #[test]
+    fn method_errors_can_be_told_apart() {
+        let e = init_gamma_method(Some("bogus"), &GammaOptions::default())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            e.downcast_ref::<MethodError>(),
+            Some(MethodError::Unknown(..))
+        ));
+        assert!(!is_connection_error(&*e));
+        assert!(!is_connection_error(&*MethodError::none_available()));
+    }
+
+    #[tokio::test]
+    async fn apply_once_with_dummy() {
+        let setting = transition::ColorSetting::neutral().with_temp(3000);
+        let options = GammaOptions::default();
+        apply_once(Some("dummy"), &options, &setting).await.unwrap();
+        assert!(apply_once(Some("bogus"), &options, &setting).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn applied_settings_are_sent() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let mut method = RecordingMethod::default();
+        let setting = transition::ColorSetting::neutral().with_temp(4500);
+
+        apply_with_notify(&mut method, &setting, &tx).await.unwrap();
+        assert_eq!(method.settings, vec![setting]);
+        assert_eq!(rx.recv().await, Some(setting));
+
+        // The receiver going away does not fail the adjustment
+        drop(rx);
+        apply_with_notify(&mut method, &setting, &tx).await.unwrap();
+        assert_eq!(method.settings.len(), 2);
+    }
+
+    /// A method that initialises but fails to start
+    struct UnstartableMethod;
+
+    impl GammaMethod for UnstartableMethod {
+        fn name(&self) -> &'static str {
+            "unstartable"
+        }
+
+        fn restore(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn adjusted_outputs(&self) -> Vec<OutputInfo> {
+            vec![]
+        }
+
+        fn set_temperature(&mut self, _: &transition::ColorSetting) -> Result<()> {
+            Ok(())
+        }
+
+        fn start(&mut self) -> Result<()> {
+            Err("no outputs".into())
+        }
+    }
+
+    /// Methods that do not touch any display, for testing the selection
+    fn test_methods(working: bool) -> HashMap<&'static str, GammaInit> {
+        let mut m: HashMap<&'static str, GammaInit> = HashMap::new();
+        m.insert("uninitialisable", |_| Err("no server".into()));
+        m.insert("unstartable", |_| Ok(Box::new(UnstartableMethod)));
+        if working {
+            m.insert("recording", |_| Ok(Box::<RecordingMethod>::default()));
+        }
+        m.insert("dummy", init_dummy);
+        m
+    }
+
+    const TEST_ORDER: [&str; 4] = ["uninitialisable", "unstartable", "recording", "dummy"];
+
+    #[tokio::test]
+    async fn fallback_ends_at_dummy() {
+        let fallback = GammaOptions {
+            fallback: true,
+            ..GammaOptions::default()
+        };
+        let no_fallback = GammaOptions::default();
+        let methods = test_methods(true);
+        let started = |name, options| start_from(&methods, &TEST_ORDER, name, options);
+        assert!(started(Some("unstartable"), &no_fallback).await.is_err());
+        assert!(started(Some("bogus"), &no_fallback).await.is_err());
+
+        // The methods are tried in order, skipping the ones that fail
+        // to initialise or to start
+        let name = |method: Result<Box<dyn GammaMethod>>| method.unwrap().name();
+        assert_eq!(name(started(None, &fallback).await), "recording");
+        assert_eq!(
+            name(started(Some("unstartable"), &fallback).await),
+            "recording"
+        );
+        assert_eq!(name(started(Some("dummy"), &fallback).await), "dummy");
+
+        let methods = test_methods(false);
+        let started = |name| start_from(&methods, &TEST_ORDER, name, &fallback);
+        assert_eq!(name(started(Some("bogus")).await), "dummy");
+        assert_eq!(name(started(None).await), "dummy");
+    }
+
+    #[tokio::test]
+    async fn detection_skips_the_dummy() {
+        let options = GammaOptions::default();
+        let methods = test_methods(true);
+        let method = init_from(&methods, &TEST_ORDER, None, &options).unwrap();
+        assert_eq!(method.name(), "unstartable");
+        let method = detect(&methods, &TEST_ORDER, &options).await.unwrap();
+        assert_eq!(method.name(), "unstartable");
+
+        let order = ["uninitialisable", "dummy"];
+        let e = init_from(&methods, &order, None, &options).err().unwrap();
+        assert!(matches!(
+            e.downcast_ref::<MethodError>(),
+            Some(MethodError::NoneAvailable)
+        ));
+        let e = detect(&methods, &order, &options).await.err().unwrap();
+        assert!(matches!(
+            e.downcast_ref::<MethodError>(),
+            Some(MethodError::NoneAvailable)
+        ));
+    }
+
+    #[test]
+    fn connect_retries_back_off() {
+        let delays = (1..=7).map(connect_retry_delay).collect::<Vec<_>>();
+        assert_eq!(delays[0], CONNECT_RETRY_DELAY);
+        assert_eq!(delays[1], CONNECT_RETRY_DELAY * 2);
+        assert_eq!(delays[2], CONNECT_RETRY_DELAY * 4);
+        assert!(delays.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(connect_retry_delay(100), MAX_CONNECT_RETRY_DELAY);
+    }
+
+    #[test]
+    fn dummy_adjusts_no_outputs() {
+        let mut dummy = init_gamma_method(Some("dummy"), &GammaOptions::default()).unwrap();
+        dummy.start().unwrap();
+        assert!(dummy.adjusted_outputs().is_empty());
+    }
+
+    #[test]
+    fn dummy_can_compute_ramps() {
+        let options = GammaOptions {
+            dummy_ramp_size: Some(256),
+            quiet: true,
+            ..GammaOptions::default()
+        };
+        let mut dummy = init_gamma_method(Some("dummy"), &options).unwrap();
+        dummy.start().unwrap();
+        assert_eq!(
+            dummy.adjusted_outputs(),
+            vec![OutputInfo {
+                id: 0,
+                ramp_size: 256
+            }]
+        );
+
+        let mut dummy = DummyMethod {
+            quiet: true,
+            fill_options: FillOptions::default(),
+            scratch: Some((vec![0; 256], vec![0; 256], vec![0; 256])),
+        };
+        dummy
+            .set_temperature(&transition::ColorSetting::neutral())
+            .unwrap();
+        let identity = (0..256).map(|i| i * 256).collect::<Vec<u16>>();
+        assert_eq!(
+            dummy.scratch,
+            Some((identity.clone(), identity.clone(), identity.clone()))
+        );
+
+        let checksum = 3 * (0..256u64).map(|i| i * 256).sum::<u64>();
+        assert_eq!(
+            ramp_summary(&identity, &identity, &identity),
+            format!(
+                "Ramp size: 256\nRamp first: 0,0,0\nRamp last: 65280,65280,65280\n\
+                 Ramp checksum: {checksum}\n"
+            )
+        );
+        assert!(ramp_summary(&[], &[], &[]).contains("Ramp first: -\n"));
+    }
+
+    #[test]
+    fn swatch_shows_the_white_point() {
+        let neutral = transition::ColorSetting::neutral();
+        assert_eq!(
+            swatch(&neutral, &FillOptions::default()),
+            "\x1b[48;2;255;255;255m      \x1b[0m"
+        );
+        assert!(swatch(&neutral.with_temp(3000), &FillOptions::default())
+            .starts_with("\x1b[48;2;255;184;109m"));
+    }
+
+    #[test]
+    fn auto_detection_order_is_stable() {
+        let order = method_priority();
+        assert_eq!(order, method_priority());
+        assert_eq!(order.last(), Some(&"dummy"));
+
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, method_names());
+
+        #[cfg(all(feature = "randr", feature = "vidmode"))]
+        {
+            let pos = |m| order.iter().position(|&n| n == m);
+            assert!(pos("randr") < pos("vidmode"));
+        }
+    }
+
+    #[test]
+    fn dummy_never_sees_changed_ramps() {
+        let mut dummy = init_gamma_method(Some("dummy"), &GammaOptions::default()).unwrap();
+        dummy.start().unwrap();
+        assert!(!dummy.ramps_changed().unwrap());
+    }
+
+    #[test]
+    fn x_methods_come_last_in_wayland_sessions() {
+        let vars = |pairs: &'static [(&str, &str)]| {
+            move |name: &str| {
+                pairs
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        assert!(is_wayland_session(vars(&[("XDG_SESSION_TYPE", "wayland")])));
+        assert!(is_wayland_session(vars(&[(
+            "WAYLAND_DISPLAY",
+            "wayland-1"
+        )])));
+        assert!(!is_wayland_session(vars(&[("XDG_SESSION_TYPE", "x11")])));
+        assert!(!is_wayland_session(vars(&[("WAYLAND_DISPLAY", "")])));
+
+        assert_eq!(detection_order(false), method_priority());
+        let order = detection_order(true);
+        assert_eq!(order.len(), method_priority().len());
+        assert_eq!(order.last(), Some(&"dummy"));
+        let first_x = order.iter().position(|name| X_METHODS.contains(name));
+        if let Some(first_x) = first_x {
+            assert!(order[first_x..order.len() - 1]
+                .iter()
+                .all(|name| X_METHODS.contains(name)));
+        }
+    }
+
+    #[test]
+    fn rect_contains_its_pixels() {
+        let rect = Rect {
+            x: 1920,
+            y: -100,
+            width: 1280,
+            height: 1024,
+        };
+        assert!(rect.contains(1920, -100));
+        assert!(rect.contains(3199, 923));
+        assert!(!rect.contains(3200, 0));
+        assert!(!rect.contains(1919, 0));
+        assert!(!rect.contains(2000, 924));
+    }
+
+    #[test]
+    fn methods_know_their_name() {
+        let dummy = init_gamma_method(Some("dummy"), &GammaOptions::default()).unwrap();
+        assert_eq!(dummy.name(), "dummy");
+        assert_eq!(RecordingMethod::default().name(), "recording");
+    }
+}
@@ -0,0 +1,46 @@
+//
+//! # Minimal implementation of Redshift in Rust
+//!
+//! aka redshift-minimal-rs
+//!
+//! The library holds the color computation and the gamma adjustment
+//! methods, the `redshift-minimal-rs` binary is a thin command-line
+//! frontend on top of it.
+//!
+
+#[macro_use]
+extern crate lazy_static;
+
+// Optional features for gamma method providers
+#[cfg(any(feature = "randr", feature = "vidmode"))]
+extern crate xcb;
+
+#[macro_use]
+pub mod log;
+
+#[cfg(target_os = "linux")]
+pub mod backlight;
+pub mod colorramp;
+pub mod config;
+pub mod gamma;
+pub mod location;
+pub mod solar;
+pub mod state;
+pub mod transition;
+
+pub use colorramp::fill;
+pub use gamma::{init_gamma_method, start_gamma_method, GammaMethod};
+pub use transition::ColorSetting;
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+// Constants
+pub const NEUTRAL_TEMP: i32 = 6500;
+pub const MIN_TEMP: i32 = 1000;
+pub const MAX_TEMP: i32 = 25000;
+pub const DAY_TEMP: i32 = 6500;
+pub const NIGHT_TEMP: i32 = 4500;
+pub const MIN_BRIGHTNESS: f64 = 0.1;
+pub const MAX_BRIGHTNESS: f64 = 1.0;
+pub const MIN_GAMMA: f64 = 0.1;
+pub const MAX_GAMMA: f64 = 10.0;
@@ -0,0 +1,163 @@
+use super::Result;
+
+use futures::StreamExt;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+
+/// Name GeoClue knows the program by, it is shown by the agents that
+/// ask the user whether to give out the location
+const DESKTOP_ID: &str = "redshift-minimal-rs";
+
+/// GCLUE_ACCURACY_LEVEL_CITY, knowing the city is plenty to follow the sun
+const ACCURACY_LEVEL_CITY: u32 = 4;
+
+/// Time to wait for GeoClue to come up with a location
+const LOCATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[zbus::proxy(
+    interface = "org.freedesktop.GeoClue2.Manager",
+    default_service = "org.freedesktop.GeoClue2",
+    default_path = "/org/freedesktop/GeoClue2/Manager"
+)]
+trait Manager {
+    fn get_client(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.GeoClue2.Client",
+    default_service = "org.freedesktop.GeoClue2"
+)]
+trait Client {
+    fn start(&self) -> zbus::Result<()>;
+
+    fn stop(&self) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn set_desktop_id(&self, id: &str) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn set_requested_accuracy_level(&self, level: u32) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn location_updated(&self, old: ObjectPath<'_>, new: ObjectPath<'_>) -> zbus::Result<()>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.GeoClue2.Location",
+    default_service = "org.freedesktop.GeoClue2"
+)]
+trait Location {
+    #[zbus(property)]
+    fn latitude(&self) -> zbus::Result<f64>;
+
+    #[zbus(property)]
+    fn longitude(&self) -> zbus::Result<f64>;
+}
+
+/// Wrapper for GeoClue errors
+#[derive(Debug)]
+pub enum GeoclueError {
+    Dbus(zbus::Error),
+    NoLocation,
+    Timeout,
+}
+
+impl GeoclueError {
+    fn dbus(e: zbus::Error) -> Box<dyn Error> {
+        Box::new(GeoclueError::Dbus(e)) as Box<dyn Error>
+    }
+
+    fn no_location() -> Box<dyn Error> {
+        Box::new(GeoclueError::NoLocation) as Box<dyn Error>
+    }
+
+    fn timeout() -> Box<dyn Error> {
+        Box::new(GeoclueError::Timeout) as Box<dyn Error>
+    }
+}
+
+impl fmt::Display for GeoclueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::GeoclueError::*;
+        // The wrapped zbus errors are reported through source()
+        match *self {
+            Dbus(..) => write!(f, "failed to get the location from GeoClue"),
+            NoLocation => write!(f, "GeoClue stopped without giving a location"),
+            Timeout => write!(
+                f,
+                "GeoClue gave no location within {} seconds",
+                LOCATION_TIMEOUT.as_secs()
+            ),
+        }
+    }
+}
+
+impl Error for GeoclueError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            GeoclueError::Dbus(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Ask GeoClue for the current latitude and longitude
+pub(super) async fn locate() -> Result<(f64, f64)> {
+    let conn = zbus::Connection::system()
+        .await
+        .map_err(GeoclueError::dbus)?;
+    let manager = ManagerProxy::new(&conn).await.map_err(GeoclueError::dbus)?;
+    let client_path = manager.get_client().await.map_err(GeoclueError::dbus)?;
+    let client = ClientProxy::builder(&conn)
+        .path(client_path)
+        .map_err(GeoclueError::dbus)?
+        .build()
+        .await
+        .map_err(GeoclueError::dbus)?;
+
+    client
+        .set_desktop_id(DESKTOP_ID)
+        .await
+        .map_err(GeoclueError::dbus)?;
+    client
+        .set_requested_accuracy_level(ACCURACY_LEVEL_CITY)
+        .await
+        .map_err(GeoclueError::dbus)?;
+
+    // Subscribe first, the first update can come right after starting
+    let mut updates = client
+        .receive_location_updated()
+        .await
+        .map_err(GeoclueError::dbus)?;
+    client.start().await.map_err(GeoclueError::dbus)?;
+
+    let update = tokio::time::timeout(LOCATION_TIMEOUT, updates.next()).await;
+    let location = match update {
+        Ok(Some(update)) => location_of(&conn, &update).await,
+        Ok(None) => Err(GeoclueError::no_location()),
+        Err(_) => Err(GeoclueError::timeout()),
+    };
+
+    if let Err(e) = client.stop().await {
+        verbose!("Failed to stop the GeoClue client: {e}");
+    }
+    location
+}
+
+/// The latitude and longitude of the location a `LocationUpdated`
+/// signal points to
+async fn location_of(conn: &zbus::Connection, update: &LocationUpdated) -> Result<(f64, f64)> {
+    let args = update.args().map_err(GeoclueError::dbus)?;
+    let location = LocationProxy::builder(conn)
+        .path(args.new().to_owned())
+        .map_err(GeoclueError::dbus)?
+        .build()
+        .await
+        .map_err(GeoclueError::dbus)?;
+
+    let lat = location.latitude().await.map_err(GeoclueError::dbus)?;
+    let lon = location.longitude().await.map_err(GeoclueError::dbus)?;
+    Ok((lat, lon))
+}
@@ -0,0 +1,79 @@
+#[cfg(feature = "geoclue")]
+mod location_geoclue;
+
+use super::Result;
+
+/// Where the latitude and longitude the sun is followed at come from
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum LocationProvider {
+    /// The coordinates given with `--location`
+    #[default]
+    Manual,
+
+    /// Ask GeoClue over D-Bus, the given coordinates are the fallback
+    Auto,
+}
+
+/// Find the latitude and longitude with the given provider
+///
+/// When GeoClue cannot be used, eg because it is not running or access
+/// to the location was denied, the manual coordinates are used instead,
+/// if there are any.
+pub async fn locate(
+    provider: LocationProvider,
+    manual: Option<(f64, f64)>,
+) -> Result<Option<(f64, f64)>> {
+    if provider == LocationProvider::Manual {
+        return Ok(manual);
+    }
+
+    match auto_locate().await {
+        Ok((lat, lon)) => {
+            verbose!("Location from GeoClue: {lat:.2}:{lon:.2}");
+            Ok(Some((lat, lon)))
+        }
+        Err(e) => match manual {
+            Some(location) => {
+                eprintln!("WARNING: {e}, using --location instead");
+                Ok(Some(location))
+            }
+            None => Err(e),
+        },
+    }
+}
+
+#[cfg(feature = "geoclue")]
+async fn auto_locate() -> Result<(f64, f64)> {
+    location_geoclue::locate().await
+}
+
+#[cfg(not(feature = "geoclue"))]
+async fn auto_locate() -> Result<(f64, f64)> {
+    Err("GeoClue support is not compiled in (feature \"geoclue\")".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn manual_location_is_used_as_is() {
+        let location = Some((51.5, -0.1));
+        assert_eq!(
+            locate(LocationProvider::Manual, location).await.unwrap(),
+            location
+        );
+        assert_eq!(locate(LocationProvider::Manual, None).await.unwrap(), None);
+    }
+
+    #[cfg(not(feature = "geoclue"))]
+    #[tokio::test]
+    async fn auto_falls_back_to_the_manual_location() {
+        let location = Some((51.5, -0.1));
+        assert_eq!(
+            locate(LocationProvider::Auto, location).await.unwrap(),
+            location
+        );
+        assert!(locate(LocationProvider::Auto, None).await.is_err());
+    }
+}
@@ -3,18 +3,26 @@
 //!
 //! aka redshift-minimal-rs
 //!
+//! The command-line frontend, all of the actual work is done by the
+//! library.
+//!
 
-#[macro_use]
-extern crate lazy_static;
-
-// Optional features for gamma method providers
-#[cfg(feature = "randr")]
-extern crate xcb;
+#[cfg(target_os = "linux")]
+use redshift_minimal_rs::backlight::Backlight;
+use redshift_minimal_rs::colorramp::{FillOptions, WhitePointTable};
+use redshift_minimal_rs::location::{self, LocationProvider};
+use redshift_minimal_rs::transition::{
+    check_brightness, check_gamma, Period, TempBounds, TransitionCurve,
+};
+use redshift_minimal_rs::{colorramp, config, gamma, log, solar, state, transition};
+use redshift_minimal_rs::{verbose, ColorSetting, Result};
+use redshift_minimal_rs::{DAY_TEMP, NEUTRAL_TEMP, NIGHT_TEMP};
 
-mod colorramp;
-mod gamma;
-mod transition;
-use transition::ColorSetting;
+use std::error::Error;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const ABOUT: &str = "
@@ -24,24 +32,226 @@ A Stripped down Rust clone of the original Redshift written in C by Jon Lund Ste
 const USAGE: &str = r#"
 USAGE:
     redshift-minimal-rs [OPTIONS]
+    redshift-minimal-rs <SUBCOMMAND> [OPTIONS]
     redshift-minimal-rs (-h | --help)
     redshift-minimal-rs (-V | --version)
 "#;
 
-pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+/// Time between two updates in continuous mode, by default
+const UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Named temperatures for `--preset`, from warmest to coolest
+const PRESETS: [(&str, i32); 5] = [
+    ("candle", 1900),
+    ("incandescent", 2700),
+    ("halogen", 3200),
+    ("daylight", 6500),
+    ("overcast", 7000),
+];
+
+/// Default of `--step`, in Kelvin
+const TEMP_STEP: i32 = 500;
 
-// Constants
-const NEUTRAL_TEMP: i32 = 6500;
-const MIN_TEMP: i32 = 1000;
-const MAX_TEMP: i32 = 25000;
+/// Brightness below which the screen is barely readable, warned about
+/// when `--brightness` is parsed
+const DIM_BRIGHTNESS: f64 = 0.2;
+
+/// Number of entries printed at each end of a ramp by `--dump-ramp`,
+/// unless `--full` is given
+const DUMP_EDGE_ENTRIES: usize = 4;
+
+/// The long flags, to suggest one when an unknown argument is given
+const LONG_FLAGS: &[&str] = &[
+    "--help",
+    "--version",
+    "--list-methods",
+    "--Set",
+    "--preset",
+    "--mired",
+    "--rgb",
+    "--adjust",
+    "--warmer",
+    "--cooler",
+    "--step",
+    "--reset",
+    "--restore",
+    "--stdin",
+    "--verbose",
+    "--method",
+    "--screen",
+    "--all-screens",
+    "--dummy-ramp-size",
+    "--card",
+    "--fallback",
+    "--dither",
+    "--whitepoint-table",
+    "--perceptual-brightness",
+    "--invert",
+    "--connect-retries",
+    "--skip-version-check",
+    "--dry-run",
+    "--crtc",
+    "--backlight",
+    "--output",
+    "--fade",
+    "--fade-on-start",
+    "--interval",
+    "--min-delta",
+    "--location",
+    "--location-provider",
+    "--period",
+    "--transition-curve",
+    "--temp",
+    "--reset-on-exit",
+    "--timeout",
+    "--reapply-on-change",
+    "--reload-outputs",
+    "--print",
+    "--dump-ramp",
+    "--full",
+    "--json",
+    "--describe",
+    "--continuous",
+    "--brightness",
+    "--brightness-rgb",
+    "--gamma",
+    "--gamma-day",
+    "--gamma-night",
+];
 
 fn usage() {
     println!("redshift-minimal-rs {VERSION}");
     println!("{ABOUT}");
     println!("{USAGE}");
     println!(
-        r#"OPTIONS:
-    -S, --Set <TEMP>      (set color temperature)
+        r#"SUBCOMMANDS:
+    set                   (one shot manual mode, the temperature is given
+                           with --temp, eg set --temp 3000 -b 0.8)
+    reset                 (same as --reset)
+    list-methods          (same as --list-methods)
+    daemon                (same as --continuous)
+
+OPTIONS:
+    -S, --Set <TEMP>      (set color temperature, - reads it from stdin)
+    -O <TEMP>             (one shot manual mode, same as -S)
+    --rgb <R:G:B>         (one shot manual mode with a fixed tint instead of
+                           a temperature, each factor between 0 and 1)
+    --preset <NAME>       (one shot manual mode with a named temperature:
+                           candle, incandescent, halogen, daylight or
+                           overcast)
+    --mired <VALUE>       (one shot manual mode with the temperature given in
+                           mireds, 1000000 / Kelvin, eg 153 for 6500K)
+    --adjust <DELTA>      (one shot manual mode, shift the last temperature
+                           that was set by DELTA)
+    --warmer, --cooler    (like --adjust, lower or raise the last temperature
+                           that was set by --step)
+    --step <KELVIN>       (step of --warmer and --cooler, defaults to 500)
+    -x, --reset           (set the neutral temperature, 6500K)
+    --restore             (write back the gamma ramps the display currently
+                           has, without computing anything)
+    -b, --brightness <B>  (set screen brightness between 0.1 and 1.0,
+                           without -S the temperature stays neutral, with -l
+                           it can be given as DAY:NIGHT)
+    --brightness-rgb <R:G:B>
+                          (scale each channel by its own brightness between
+                           0.1 and 1.0, on top of -b)
+    --backlight <B>       (Linux only, also set the backlight of the panel
+                           to B between 0.1 and 1.0 of its maximum)
+    -g, --gamma <R:G:B>   (set gamma, either one value or one per channel)
+    --gamma-day <R:G:B>   (gamma during the day with -l)
+    --gamma-night <R:G:B> (gamma during the night with -l)
+    -m, --method <NAME>   (gamma adjustment method to use)
+    --fallback            (when the method fails, try the other ones and
+                           finally the dummy instead of giving up)
+    --fade <MS>           (fade from neutral to the temperature in MS
+                           milliseconds, one shot mode only)
+    --fade-on-start <MS>  (like --fade, and in continuous mode fade in the
+                           first temperature at launch)
+    -l, --location <LAT:LON>
+                          (set the temperature from the position of the sun,
+                           north and east are positive)
+    --location-provider <PROVIDER>
+                          (manual to use -l, or auto to ask GeoClue for the
+                           location, with -l as the fallback, defaults to
+                           manual)
+    -t, --temp <DAY:NIGHT>
+                          (day and night temperatures for -l, defaults to
+                           6500:4500)
+    --period <PERIOD>     (force day, night or transition rather than
+                           following the sun, day and night use the -t
+                           temperatures)
+    --transition-curve <CURVE>
+                          (easing of the transition between day and night:
+                           linear, cubic or sine, defaults to linear)
+    -c, --continuous      (keep applying the color setting until interrupted)
+    --interval <SECONDS>  (time between two updates in continuous mode,
+                           defaults to 5)
+    --min-delta <KELVIN>  (in continuous mode, only change the temperature
+                           once it moved by more than KELVIN since the last
+                           change)
+    --reapply-on-change <SECONDS>
+                          (randr only, in continuous mode, check every
+                           SECONDS whether the gamma ramps were reset, eg by
+                           the driver, and set them again if so)
+    --reload-outputs      (in continuous mode, also restart the gamma method
+                           and look for outputs again on SIGHUP)
+    --stdin               (apply each temperature read from stdin, one per
+                           line, and restore the original colors at the end
+                           of input)
+    --reset-on-exit       (in one shot mode, keep running and restore the
+                           original colors on Ctrl-C or SIGTERM)
+    --timeout <SECONDS>   (in one shot mode, restore the original colors
+                           after SECONDS, or on Ctrl-C or SIGTERM before)
+    -p, --print           (print the RGB white point of the temperature and
+                           exit without touching the display)
+    --dump-ramp           (print the gamma ramps the outputs currently have
+                           as CSV, without changing them)
+    --full                (with --dump-ramp, print every entry instead of
+                           the first and last few)
+    --describe            (print what the temperature looks like, eg
+                           "3000K - soft white (halogen-ish)")
+    --json                (print what was applied as a JSON object on stdout,
+                           one per update in continuous mode)
+    -v, --verbose         (print what is being done to stderr)
+    --list-methods        (list the available gamma adjustment methods)
+    --screen <N>          (X screen to use, defaults to the one of $DISPLAY)
+    --all-screens         (randr only, adjust every X screen instead of
+                           only one)
+    --card <N>            (DRM card to use, defaults to 0)
+    --dummy-ramp-size <N> (with -m dummy, compute gamma ramps of size N and
+                           print their first and last entries and checksum)
+    --crtc <N[,N...]>     (only adjust the CRTCs with these indices,
+                           may be given more than once)
+    --crtc <N=TEMP[,...]> (randr only, use another temperature for the CRTC
+                           with this index)
+    --output <NAME[,...]> (randr only, only adjust the outputs with these
+                           names, eg HDMI-1, may be given more than once)
+    --perceptual-brightness
+                          (scale the ramps so that -b gives the perceived
+                           lightness rather than a linear factor, 0.5 looks
+                           about half as bright)
+    --invert              (invert the colors, after applying the
+                           temperature and brightness)
+    --whitepoint-table <PATH>
+                          (use the blackbody white points from this file,
+                           one `TEMP R G B` line per 100K from 1000K to
+                           25100K, instead of the built-in ones)
+    --dither              (dither the gamma ramps to reduce banding on
+                           low bit depth panels)
+    --connect-retries <N> (randr and vidmode, retry connecting to an X server
+                           that is not ready yet N times, waiting longer
+                           every time, defaults to 3)
+    --skip-version-check  (randr only, try adjusting even when the server
+                           supports a RandR version older than 1.3)
+    --dry-run             (randr only, compute the gamma ramps without
+                           sending them to the display)
+
+EXIT STATUS:
+    0  success
+    1  any other failure
+    2  invalid arguments, environment variables or configuration file
+    3  no gamma adjustment method available
+    4  the display server could not be reached
 "#
     );
 }
@@ -49,18 +259,60 @@ fn usage() {
 /// Selected run mode
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 enum Mode {
-    /// Reset the screen
+    /// Reset the screen by forcing the neutral temperature
     Reset,
 
+    /// Read the current gamma ramps and write them back unchanged,
+    /// exercising the restore path of the method
+    Restore,
+
     /// One shot manual mode - set color temperature
     Manual(i32),
+
+    /// One shot manual mode - shift the last set color temperature
+    /// by the given delta
+    Adjust(i32),
+
+    /// Continual mode - keep applying the color temperature until
+    /// interrupted, then restore the original gamma ramps
+    Continuous(i32),
+
+    /// Apply every temperature read from stdin, one per line, then
+    /// restore the original gamma ramps at the end of input
+    Stdin,
+}
+
+/// The subcommands, which select a mode like the corresponding flags
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Subcommand {
+    /// One shot manual mode, taking the temperature as `--temp`
+    Set,
+    Reset,
+    ListMethods,
+    Daemon,
+}
+
+impl Subcommand {
+    fn parse(s: &str) -> Option<Subcommand> {
+        match s {
+            "set" => Some(Subcommand::Set),
+            "reset" => Some(Subcommand::Reset),
+            "list-methods" => Some(Subcommand::ListMethods),
+            "daemon" => Some(Subcommand::Daemon),
+            _ => None,
+        }
+    }
 }
 
 impl Mode {
     fn as_args(&self) -> &str {
         match self {
             Mode::Reset => "--reset|-x",
-            Mode::Manual(..) => "--Set|-S",
+            Mode::Restore => "--restore",
+            Mode::Manual(..) => "--Set|-S|-O|--preset|--rgb|--mired",
+            Mode::Adjust(..) => "--adjust|--warmer|--cooler",
+            Mode::Continuous(..) => "--continuous|-c",
+            Mode::Stdin => "--stdin",
         }
     }
 }
@@ -68,111 +320,2432 @@ impl Mode {
 struct Args {
     pub help: bool,
     pub version: bool,
+    pub list_methods: bool,
+    pub self_check: bool,
+    pub verbose: bool,
     pub method: Option<String>,
     pub mode: Mode,
+    pub brightness: f64,
+    pub night_brightness: f64,
+    pub brightness_rgb: [f64; 3],
+    pub gamma: [f64; 3],
+    pub night_gamma: [f64; 3],
+    pub rgb: Option<[f64; 3]>,
+    pub gamma_options: gamma::GammaOptions,
+    pub temp_bounds: TempBounds,
+    pub fade: Option<Duration>,
+    pub fade_on_start: Option<Duration>,
+    pub backlight: Option<f64>,
+    pub interval: Duration,
+    pub min_delta: Option<i32>,
+    pub reload_outputs: bool,
+    pub reapply_interval: Option<Duration>,
+    pub location: Option<(f64, f64)>,
+    pub location_provider: LocationProvider,
+    pub day_temp: i32,
+    pub night_temp: i32,
+    pub period: Period,
+    pub transition_curve: TransitionCurve,
+    pub reset_on_exit: bool,
+    pub timeout: Option<Duration>,
+    pub print: bool,
+    pub whitepoint_table: Option<PathBuf>,
+    pub json: bool,
+    pub describe: bool,
+    pub dump_ramp: bool,
+    pub full: bool,
 }
 
 impl Args {
     pub fn defaults() -> Args {
+        let temp_bounds = TempBounds::default();
         Args {
             help: false,
             version: false,
+            list_methods: false,
+            self_check: false,
+            verbose: false,
             method: None,
-            mode: Mode::Manual(NEUTRAL_TEMP),
+            mode: Mode::Manual(temp_bounds.neutral),
+            brightness: 1.0,
+            night_brightness: 1.0,
+            brightness_rgb: [1.0, 1.0, 1.0],
+            gamma: [1.0, 1.0, 1.0],
+            night_gamma: [1.0, 1.0, 1.0],
+            rgb: None,
+            gamma_options: gamma::GammaOptions::default(),
+            temp_bounds,
+            fade: None,
+            fade_on_start: None,
+            backlight: None,
+            interval: UPDATE_INTERVAL,
+            min_delta: None,
+            reload_outputs: false,
+            reapply_interval: None,
+            location: None,
+            location_provider: LocationProvider::Manual,
+            day_temp: DAY_TEMP,
+            night_temp: NIGHT_TEMP,
+            period: Period::Transition,
+            transition_curve: TransitionCurve::Linear,
+            reset_on_exit: false,
+            timeout: None,
+            print: false,
+            whitepoint_table: None,
+            json: false,
+            describe: false,
+            dump_ramp: false,
+            full: false,
         }
     }
 
-    /// Parse the command-line arguments into a Redshift configuration
-    pub fn update_from_args(mut self) -> Result<Args> {
-        let args = std::env::args().skip(1).collect::<Vec<_>>();
+    /// How long to fade from the neutral temperature to the first color
+    /// setting applied
+    ///
+    /// `--fade` only fades in one shot mode, `--fade-on-start` also at
+    /// the launch of continuous mode.
+    fn launch_fade(&self) -> Option<Duration> {
+        match self.mode {
+            Mode::Continuous(..) => self.fade_on_start,
+            _ => self.fade.or(self.fade_on_start),
+        }
+    }
 
-        if args[0] == "-h" || args[0] == "--help" {
-            self.help = true;
-            // A short-cut: We should just print the usage and exit, so no need
-            // to run any subsequent checks.
-            return Ok(self);
+    /// The color setting to apply at the given time
+    ///
+    /// With a location, the temperature follows the position of the sun,
+    /// otherwise the given temperature is used. A day or night period
+    /// pins the temperature to the day or night one. The brightness and
+    /// gamma are blended between their day and night values the same way.
+    fn color_setting(&self, temp: i32, time: SystemTime) -> Result<ColorSetting> {
+        let setting = ColorSetting::new(&self.temp_bounds, temp, self.gamma, self.brightness)?
+            .with_brightness_rgb(self.brightness_rgb)
+            .with_white_point(self.rgb);
+
+        let alpha = match (self.period, self.location) {
+            (Period::Day, _) => 0.0,
+            (Period::Night, _) => 1.0,
+            (Period::Transition, Some((lat, lon))) => {
+                let unix_time = time
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                let elevation = solar::solar_elevation(unix_time, lat, lon);
+                self.transition_curve
+                    .apply(transition::elevation_alpha(elevation))
+            }
+            (Period::Transition, None) => return Ok(setting),
+        };
+
+        Ok(transition::interpolate_color_settings(
+            &setting.with_temp(self.day_temp),
+            &setting
+                .with_temp(self.night_temp)
+                .with_brightness(self.night_brightness)
+                .with_gamma(self.night_gamma),
+            alpha,
+        ))
+    }
+
+    /// Apply the settings of the configuration file
+    ///
+    /// This happens before parsing the command-line arguments, so that
+    /// flags override the file.
+    pub fn update_from_config(mut self, config: &config::Config) -> Result<Args> {
+        if let Some(t) = config.temp {
+            self.mode = Mode::Manual(self.temp_bounds.check(t)?);
+        }
+        if let Some(b) = config.brightness {
+            self.brightness = check_brightness(b)?;
+            self.night_brightness = self.brightness;
         }
+        if let Some(ref g) = config.gamma {
+            self.gamma = parse_gamma(g)?;
+            self.night_gamma = self.gamma;
+        }
+        if let Some(ref m) = config.method {
+            self.method = Some(m.clone());
+        }
+        Ok(self)
+    }
+
+    /// Apply the `REDSHIFT_METHOD`, `REDSHIFT_TEMP` and
+    /// `REDSHIFT_BRIGHTNESS` environment variables
+    ///
+    /// This happens after reading the configuration file and before
+    /// parsing the command-line arguments.
+    pub fn update_from_env(self) -> Args {
+        self.update_from_vars(|name| std::env::var(name).ok())
+    }
 
-        if args[0] == "-V" || args[0] == "--version" {
-            self.version = true;
-            return Ok(self);
+    /// Apply the environment variables as given by `var`
+    ///
+    /// Malformed values only give a warning and are otherwise ignored.
+    fn update_from_vars(mut self, var: impl Fn(&str) -> Option<String>) -> Args {
+        if let Some(m) = var("REDSHIFT_METHOD") {
+            self.method = Some(m);
+        }
+        if let Some(t) = var("REDSHIFT_TEMP") {
+            match parse_temp(&t).and_then(|t| self.temp_bounds.check(t)) {
+                Ok(t) => self.mode = Mode::Manual(t),
+                Err(e) => eprintln!("WARNING: ignoring REDSHIFT_TEMP: {e}"),
+            }
+        }
+        if let Some(b) = var("REDSHIFT_BRIGHTNESS") {
+            match parse_brightness(&b) {
+                Ok(b) => (self.brightness, self.night_brightness) = b,
+                Err(e) => eprintln!("WARNING: ignoring REDSHIFT_BRIGHTNESS: {e}"),
+            }
+        }
+        self
+    }
+
+    /// Parse the command-line arguments into a Redshift configuration
+    pub fn update_from_args(self) -> Result<Args> {
+        self.parse_args(std::env::args().skip(1))
+    }
+
+    /// Parse the given arguments (without the program name)
+    ///
+    /// No arguments at all means the defaults are used. The first one
+    /// can be a subcommand, the flags that follow it are the same as
+    /// without one.
+    fn parse_args(mut self, args: impl IntoIterator<Item = String>) -> Result<Args> {
+        let mut args = args.into_iter().peekable();
+        let subcommand = args.peek().and_then(|a| Subcommand::parse(a));
+        if subcommand.is_some() {
+            args.next();
         }
 
         // Detect the mode
-        // All four are mutually excluse (at most one of them may be present)
+        // Manual (-O, -S, --preset, --mired, --rgb), adjust (--adjust,
+        // --warmer, --cooler), reset, restore and stdin are mutually
+        // exclusive (at most one of them may be present), -c only goes
+        // with manual
         let mut mode: Option<Mode> = None;
+        let mut continuous = false;
+        match subcommand {
+            Some(Subcommand::Reset) => mode = Some(Mode::Reset),
+            Some(Subcommand::ListMethods) => {
+                self.list_methods = true;
+                return Ok(self);
+            }
+            Some(Subcommand::Daemon) => continuous = true,
+            Some(Subcommand::Set) | None => {}
+        }
+        // Direction of --warmer and --cooler, the step may come after them
+        let mut step_sign = None;
+        let mut step = TEMP_STEP;
+        // Whether -b gave a separate night brightness
+        let mut night_brightness = false;
 
-        if args[0] == "-S" || args[0] == "--Set" {
-            if args.len() < 2 {
-                return Err("Missing argument for -S".into());
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    self.help = true;
+                    // A short-cut: We should just print the usage and exit, so no need
+                    // to run any subsequent checks.
+                    return Ok(self);
+                }
+                "-V" | "--version" => {
+                    self.version = true;
+                    return Ok(self);
+                }
+                "--list-methods" => {
+                    self.list_methods = true;
+                    return Ok(self);
+                }
+                // Hidden, for debugging the color math
+                "--self-check" => {
+                    self.self_check = true;
+                    return Ok(self);
+                }
+                // -O is the one shot manual mode of the C redshift, set
+                // takes the temperature as --temp
+                a if matches!(a, "-S" | "--Set" | "-O")
+                    || (subcommand == Some(Subcommand::Set) && matches!(a, "-t" | "--temp")) =>
+                {
+                    let t = match args.next() {
+                        Some(t) if t == "-" => read_temp(std::io::stdin().lock())?,
+                        Some(t) => parse_temp(&t)?,
+                        None => return Err(format!("Missing argument for {arg}").into()),
+                    };
+                    let t = self.temp_bounds.check(t)?;
+                    mode = Some(check_mode(mode, Mode::Manual(t))?);
+                }
+                "--preset" => {
+                    let t = match args.next() {
+                        Some(p) => parse_preset(&p)?,
+                        None => return Err("Missing argument for --preset".into()),
+                    };
+                    let t = self.temp_bounds.check(t)?;
+                    mode = Some(check_mode(mode, Mode::Manual(t))?);
+                }
+                "--mired" => {
+                    let t = match args.next() {
+                        Some(m) => parse_mired(&m)?,
+                        None => return Err("Missing argument for --mired".into()),
+                    };
+                    let t = self.temp_bounds.check(t)?;
+                    mode = Some(check_mode(mode, Mode::Manual(t))?);
+                }
+                "--rgb" => {
+                    self.rgb = match args.next() {
+                        Some(c) => Some(parse_rgb(&c)?),
+                        None => return Err("Missing argument for --rgb".into()),
+                    };
+                    let t = self.temp_bounds.neutral;
+                    mode = Some(check_mode(mode, Mode::Manual(t))?);
+                }
+                "--adjust" => {
+                    let delta = match args.next() {
+                        Some(d) => d.parse::<i32>().or_else(|_| {
+                            malformed(format!("Temperature delta must be a number (was '{d}')"))
+                        })?,
+                        None => return Err("Missing argument for --adjust".into()),
+                    };
+                    mode = Some(check_mode(mode, Mode::Adjust(delta))?);
+                }
+                "--warmer" | "--cooler" => {
+                    let sign = if arg == "--warmer" { -1 } else { 1 };
+                    mode = Some(check_mode(mode, Mode::Adjust(sign * step))?);
+                    step_sign = Some(sign);
+                }
+                "--step" => {
+                    step = match args.next() {
+                        Some(s) => match parse_temp(&s) {
+                            Ok(step) if step > 0 => step,
+                            _ => {
+                                return malformed(format!(
+                                    "Step must be a positive temperature (was '{s}')"
+                                ))
+                            }
+                        },
+                        None => return Err("Missing argument for --step".into()),
+                    };
+                }
+                "-x" | "--reset" => {
+                    mode = Some(check_mode(mode, Mode::Reset)?);
+                }
+                "--restore" => {
+                    mode = Some(check_mode(mode, Mode::Restore)?);
+                }
+                "--stdin" => {
+                    mode = Some(check_mode(mode, Mode::Stdin)?);
+                }
+                "-v" | "--verbose" => {
+                    self.verbose = true;
+                }
+                "-m" | "--method" => {
+                    self.method = match args.next() {
+                        Some(m) => Some(m),
+                        None => return Err("Missing argument for -m".into()),
+                    };
+                }
+                "--screen" => {
+                    self.gamma_options.screen = match args.next() {
+                        Some(n) => Some(n.parse::<usize>().or_else(|_| {
+                            malformed(format!("Screen must be a screen number (was '{n}')"))
+                        })?),
+                        None => return Err("Missing argument for --screen".into()),
+                    };
+                }
+                "--all-screens" => {
+                    self.gamma_options.all_screens = true;
+                }
+                "--dummy-ramp-size" => {
+                    self.gamma_options.dummy_ramp_size = match args.next() {
+                        Some(n) => match n.parse::<usize>() {
+                            Ok(size) if size > 0 => Some(size),
+                            _ => {
+                                return malformed(format!(
+                                    "Ramp size must be a positive number (was '{n}')"
+                                ))
+                            }
+                        },
+                        None => return Err("Missing argument for --dummy-ramp-size".into()),
+                    };
+                }
+                "--card" => {
+                    self.gamma_options.card = match args.next() {
+                        Some(c) => c.parse::<usize>().or_else(|_| {
+                            malformed(format!("Card must be a card number (was '{c}')"))
+                        })?,
+                        None => return Err("Missing argument for --card".into()),
+                    };
+                }
+                "--fallback" => {
+                    self.gamma_options.fallback = true;
+                }
+                "--dither" => {
+                    self.gamma_options.fill.dither = true;
+                }
+                "--whitepoint-table" => {
+                    self.whitepoint_table = match args.next() {
+                        Some(p) => Some(PathBuf::from(p)),
+                        None => return Err("Missing argument for --whitepoint-table".into()),
+                    };
+                }
+                "--perceptual-brightness" => {
+                    self.gamma_options.fill.perceptual_brightness = true;
+                }
+                "--invert" => {
+                    self.gamma_options.fill.invert = true;
+                }
+                "--connect-retries" => {
+                    self.gamma_options.connect_retries = match args.next() {
+                        Some(n) => n.parse::<usize>().or_else(|_| {
+                            malformed(format!("Retries must be a number (was '{n}')"))
+                        })?,
+                        None => return Err("Missing argument for --connect-retries".into()),
+                    };
+                }
+                "--skip-version-check" => {
+                    self.gamma_options.skip_version_check = true;
+                }
+                "--dry-run" => {
+                    self.gamma_options.dry_run = true;
+                }
+                "--crtc" => {
+                    let crtcs = match args.next() {
+                        Some(c) => parse_crtcs(&c, &self.temp_bounds)?,
+                        None => return Err("Missing argument for --crtc".into()),
+                    };
+                    for (n, temp) in crtcs {
+                        match temp {
+                            Some(t) => {
+                                self.gamma_options.crtc_temps.insert(n, t);
+                            }
+                            None => self.gamma_options.crtcs.push(n),
+                        }
+                    }
+                    self.gamma_options.crtcs.sort_unstable();
+                    self.gamma_options.crtcs.dedup();
+                }
+                "--backlight" => {
+                    self.backlight = match args.next() {
+                        Some(b) => Some(
+                            b.parse::<f64>()
+                                .or_else(|_| {
+                                    malformed(format!("Backlight must be a number (was '{b}')"))
+                                })
+                                .and_then(check_brightness)?,
+                        ),
+                        None => return Err("Missing argument for --backlight".into()),
+                    };
+                }
+                "--output" => {
+                    match args.next() {
+                        Some(o) => self
+                            .gamma_options
+                            .outputs
+                            .extend(o.split(',').map(String::from)),
+                        None => return Err("Missing argument for --output".into()),
+                    };
+                }
+                flag @ ("--fade" | "--fade-on-start") => {
+                    let fade = match args.next() {
+                        Some(ms) => {
+                            Some(Duration::from_millis(ms.parse::<u64>().or_else(|_| {
+                                malformed(format!("Fade must be in milliseconds (was '{ms}')"))
+                            })?))
+                        }
+                        None => return Err(format!("Missing argument for {flag}").into()),
+                    };
+                    if flag == "--fade" {
+                        self.fade = fade;
+                    } else {
+                        self.fade_on_start = fade;
+                    }
+                }
+                "--interval" => {
+                    self.interval = match args.next() {
+                        Some(s) => parse_interval(&s)?,
+                        None => return Err("Missing argument for --interval".into()),
+                    };
+                }
+                "--min-delta" => {
+                    self.min_delta = match args.next() {
+                        Some(s) => Some(parse_min_delta(&s)?),
+                        None => return Err("Missing argument for --min-delta".into()),
+                    };
+                }
+                "-l" | "--location" => {
+                    self.location = match args.next() {
+                        Some(l) => Some(solar::parse_location(&l)?),
+                        None => return Err("Missing argument for -l".into()),
+                    };
+                }
+                "--period" => {
+                    self.period = match args.next() {
+                        Some(p) => parse_period(&p)?,
+                        None => return Err("Missing argument for --period".into()),
+                    };
+                }
+                "--location-provider" => {
+                    self.location_provider = match args.next() {
+                        Some(p) => parse_location_provider(&p)?,
+                        None => return Err("Missing argument for --location-provider".into()),
+                    };
+                }
+                "--transition-curve" => {
+                    self.transition_curve = match args.next() {
+                        Some(c) => parse_transition_curve(&c)?,
+                        None => return Err("Missing argument for --transition-curve".into()),
+                    };
+                }
+                "-t" | "--temp" => {
+                    (self.day_temp, self.night_temp) = match args.next() {
+                        Some(t) => parse_day_night(&t, &self.temp_bounds)?,
+                        None => return Err("Missing argument for -t".into()),
+                    };
+                }
+                "--reset-on-exit" => {
+                    self.reset_on_exit = true;
+                }
+                "--timeout" => {
+                    self.timeout = match args.next() {
+                        Some(s) => Some(parse_interval(&s)?),
+                        None => return Err("Missing argument for --timeout".into()),
+                    };
+                }
+                "--reapply-on-change" => {
+                    self.reapply_interval = match args.next() {
+                        Some(s) => Some(parse_interval(&s)?),
+                        None => return Err("Missing argument for --reapply-on-change".into()),
+                    };
+                }
+                "--reload-outputs" => {
+                    self.reload_outputs = true;
+                }
+                "-p" | "--print" => {
+                    self.print = true;
+                }
+                "--dump-ramp" => {
+                    self.dump_ramp = true;
+                }
+                "--full" => {
+                    self.full = true;
+                }
+                "--describe" => {
+                    self.describe = true;
+                }
+                "--json" => {
+                    self.json = true;
+                    self.gamma_options.quiet = true;
+                }
+                "-c" | "--continuous" => {
+                    continuous = true;
+                }
+                "-b" | "--brightness" => {
+                    (self.brightness, self.night_brightness) = match args.next() {
+                        Some(b) => {
+                            night_brightness = b.contains(':');
+                            parse_brightness(&b)?
+                        }
+                        None => return Err("Missing argument for -b".into()),
+                    };
+                }
+                "--brightness-rgb" => {
+                    self.brightness_rgb = match args.next() {
+                        Some(b) => parse_brightness_rgb(&b)?,
+                        None => return Err("Missing argument for --brightness-rgb".into()),
+                    };
+                }
+                "-g" | "--gamma" => {
+                    self.gamma = match args.next() {
+                        Some(g) => parse_gamma(&g)?,
+                        None => return Err("Missing argument for -g".into()),
+                    };
+                    self.night_gamma = self.gamma;
+                }
+                "--gamma-day" => {
+                    self.gamma = match args.next() {
+                        Some(g) => parse_gamma(&g)?,
+                        None => return Err("Missing argument for --gamma-day".into()),
+                    };
+                }
+                "--gamma-night" => {
+                    self.night_gamma = match args.next() {
+                        Some(g) => parse_gamma(&g)?,
+                        None => return Err("Missing argument for --gamma-night".into()),
+                    };
+                }
+                _ => {
+                    return match suggest_flag(&arg) {
+                        Some(flag) => {
+                            malformed(format!("Unknown argument '{arg}' (did you mean '{flag}'?)"))
+                        }
+                        None => malformed(format!("Unknown argument '{arg}'")),
+                    }
+                }
             }
-            let t = args[1].parse::<i32>().unwrap();
+        }
+
+        if let Some(sign) = step_sign {
+            mode = Some(Mode::Adjust(sign * step));
+        }
 
-            if !(MIN_TEMP..=MAX_TEMP).contains(&t) {
+        if subcommand == Some(Subcommand::Set) {
+            if let Some(m @ (Mode::Reset | Mode::Restore | Mode::Stdin)) = mode {
+                return malformed(format!(
+                    "Mode '{}' cannot be used in conjuction with 'set'",
+                    m.as_args()
+                ));
+            }
+            if continuous {
                 return malformed(format!(
-                    "Temperature must be between {MIN_TEMP} and {MAX_TEMP} (was {t})",
+                    "Mode '{}' cannot be used in conjuction with 'set'",
+                    Mode::Continuous(NEUTRAL_TEMP).as_args()
                 ));
             }
-            mode = Some(Mode::Manual(t));
         }
 
-        if args[0] == "-x" || args[0] == "--reset" {
-            if let Some(m) = mode {
+        if let (Some(m), Some(_)) = (mode, self.location) {
+            return malformed(format!(
+                "Mode '{}' cannot be used in conjuction with '--location|-l'",
+                m.as_args()
+            ));
+        }
+
+        if let (Some(m), LocationProvider::Auto) = (mode, self.location_provider) {
+            return malformed(format!(
+                "Mode '{}' cannot be used in conjuction with '--location-provider auto'",
+                m.as_args()
+            ));
+        }
+
+        if let (Some(m), Period::Day | Period::Night) = (mode, self.period) {
+            return malformed(format!(
+                "Mode '{}' cannot be used in conjuction with '--period'",
+                m.as_args()
+            ));
+        }
+
+        // Without a location or period only the day brightness is used
+        if let (true, None, LocationProvider::Manual, Period::Transition) = (
+            night_brightness,
+            self.location,
+            self.location_provider,
+            self.period,
+        ) {
+            return malformed(
+                "Brightness 'DAY:NIGHT' needs '--location|-l', '--location-provider auto' or '--period'"
+                    .to_string(),
+            );
+        }
+
+        self.mode = match (mode.unwrap_or(self.mode), continuous) {
+            (Mode::Manual(t), true) => Mode::Continuous(t),
+            (m, true) => {
                 return malformed(format!(
                     "Mode '{}' cannot be used in conjuction with '{}'",
-                    Mode::Reset.as_args(),
+                    Mode::Continuous(NEUTRAL_TEMP).as_args(),
                     m.as_args()
-                ));
+                ))
             }
-            mode = Some(Mode::Reset);
-        }
+            (m, false) => m,
+        };
 
-        self.mode = mode.unwrap_or(self.mode);
+        if let (Some(_), Mode::Reset | Mode::Restore | Mode::Continuous(..) | Mode::Stdin) =
+            (self.timeout, self.mode)
+        {
+            return malformed(format!(
+                "Mode '{}' cannot be used in conjuction with '--timeout'",
+                self.mode.as_args()
+            ));
+        }
 
         Ok(self)
     }
 }
 
+/// Make sure at most one mode is selected on the command line
+fn check_mode(current: Option<Mode>, new: Mode) -> Result<Mode> {
+    match current {
+        Some(m) => malformed(format!(
+            "Mode '{}' cannot be used in conjuction with '{}'",
+            new.as_args(),
+            m.as_args()
+        )),
+        None => Ok(new),
+    }
+}
+
+/// The long flag closest to an unknown argument, if it looks like a typo
+/// of one
+///
+/// It is a typo when at most a third of the flag has to be edited to
+/// get the argument, and at most 3 characters. Failing that, the longest
+/// flag the argument starts with is taken (eg `--temp` for
+/// `--temperature`).
+fn suggest_flag(arg: &str) -> Option<&'static str> {
+    if !arg.starts_with("--") {
+        return None;
+    }
+    LONG_FLAGS
+        .iter()
+        .map(|&flag| (edit_distance(arg, flag), flag))
+        .filter(|&(distance, flag)| distance <= (flag.len() / 3).min(3))
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, flag)| flag)
+        .or_else(|| {
+            LONG_FLAGS
+                .iter()
+                .copied()
+                .filter(|flag| arg.starts_with(flag))
+                .max_by_key(|flag| flag.len())
+        })
+}
+
+/// Number of characters to insert, delete or replace to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let replace = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replace.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Parse a period given as `day`, `night` or `transition`
+fn parse_period(s: &str) -> Result<Period> {
+    match s {
+        "day" => Ok(Period::Day),
+        "night" => Ok(Period::Night),
+        "transition" => Ok(Period::Transition),
+        _ => malformed(format!(
+            "Period must be one of day, night or transition (was '{s}')"
+        )),
+    }
+}
+
+/// Parse a transition curve given as `linear`, `cubic` or `sine`
+fn parse_transition_curve(s: &str) -> Result<TransitionCurve> {
+    match s {
+        "linear" => Ok(TransitionCurve::Linear),
+        "cubic" => Ok(TransitionCurve::Cubic),
+        "sine" => Ok(TransitionCurve::Sine),
+        _ => malformed(format!(
+            "Transition curve must be one of linear, cubic or sine (was '{s}')"
+        )),
+    }
+}
+
+/// Parse a location provider given as `manual` or `auto`
+fn parse_location_provider(s: &str) -> Result<LocationProvider> {
+    match s {
+        "manual" => Ok(LocationProvider::Manual),
+        "auto" => Ok(LocationProvider::Auto),
+        _ => malformed(format!(
+            "Location provider must be either manual or auto (was '{s}')"
+        )),
+    }
+}
+
+/// Parse the update interval of continuous mode, in seconds
+fn parse_interval(s: &str) -> Result<Duration> {
+    match s.parse::<f64>().map(Duration::try_from_secs_f64) {
+        Ok(Ok(interval)) if !interval.is_zero() => Ok(interval),
+        _ => malformed(format!(
+            "Interval must be a positive number of seconds (was '{s}')"
+        )),
+    }
+}
+
+/// Parse the `--min-delta` of continuous mode, in Kelvin
+fn parse_min_delta(s: &str) -> Result<i32> {
+    match parse_temp(s) {
+        Ok(delta) if delta >= 0 => Ok(delta),
+        _ => malformed(format!(
+            "Minimum delta must be a non-negative temperature (was '{s}')"
+        )),
+    }
+}
+
+/// Parse the day and night temperatures given as `DAY:NIGHT`
+fn parse_day_night(s: &str, bounds: &TempBounds) -> Result<(i32, i32)> {
+    let (day, night) = match s.split_once(':') {
+        Some((day, night)) => (parse_temp(day), parse_temp(night)),
+        None => return malformed(format!("Temperatures must be DAY:NIGHT (was '{s}')")),
+    };
+    let (day, night) = match (day, night) {
+        (Ok(day), Ok(night)) => (bounds.check(day)?, bounds.check(night)?),
+        _ => return malformed(format!("Temperatures must be numbers (was '{s}')")),
+    };
+
+    if day < night {
+        return malformed(format!(
+            "Day temperature must not be lower than night temperature (was {day}:{night})"
+        ));
+    }
+    Ok((day, night))
+}
+
+/// Parse a temperature in Kelvin, with or without a `K` suffix
+fn parse_temp(s: &str) -> Result<i32> {
+    s.strip_suffix(['k', 'K'])
+        .unwrap_or(s)
+        .parse::<i32>()
+        .or_else(|_| malformed(format!("Temperature must be a number (was '{s}')")))
+}
+
+/// Parse a `--mired` value into a temperature in Kelvin
+fn parse_mired(s: &str) -> Result<i32> {
+    match s.parse::<f64>() {
+        Ok(mired) if mired > 0.0 && mired.is_finite() => Ok(mired_to_kelvin(mired)),
+        _ => malformed(format!("Mired must be a positive number (was '{s}')")),
+    }
+}
+
+/// Convert micro reciprocal degrees to Kelvin, rounded to the nearest
+/// Kelvin
+fn mired_to_kelvin(mired: f64) -> i32 {
+    (1_000_000.0 / mired).round().min(i32::MAX as f64) as i32
+}
+
+/// Look up the temperature of a `--preset` name
+fn parse_preset(s: &str) -> Result<i32> {
+    match PRESETS.iter().find(|(name, _)| *name == s) {
+        Some(&(_, t)) => Ok(t),
+        None => malformed(format!(
+            "Preset must be one of {} (was '{s}')",
+            PRESETS
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Read a temperature from the first line of `input`, for `-S -`
+fn read_temp(mut input: impl BufRead) -> Result<i32> {
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) => malformed("Expected a temperature on stdin, got end of input".to_string()),
+        Ok(_) => parse_temp(line.trim()),
+        Err(e) => malformed(format!("Failed to read the temperature from stdin: {e}")),
+    }
+}
+
+/// Parse a brightness, given either as a single value or as `DAY:NIGHT`
+fn parse_brightness(s: &str) -> Result<(f64, f64)> {
+    let parse = |b: &str| {
+        let b = b
+            .parse::<f64>()
+            .or_else(|_| malformed(format!("Brightness must be a number (was '{b}')")))
+            .and_then(check_brightness)?;
+        if b < DIM_BRIGHTNESS {
+            eprintln!("WARNING: brightness {b} leaves the screen barely readable, '-x' resets it");
+        }
+        Ok(b)
+    };
+
+    match s.split_once(':') {
+        Some((day, night)) => Ok((parse(day)?, parse(night)?)),
+        None => parse(s).map(|b| (b, b)),
+    }
+}
+
+/// Parse a colon-separated `R:G:B` triple of channel brightnesses
+fn parse_brightness_rgb(s: &str) -> Result<[f64; 3]> {
+    let values = s
+        .split(':')
+        .map(|b| {
+            b.parse::<f64>()
+                .or_else(|_| malformed(format!("Brightness must be a number (was '{b}')")))
+                .and_then(check_brightness)
+        })
+        .collect::<Result<Vec<f64>>>()?;
+
+    match values[..] {
+        [r, g, b] => Ok([r, g, b]),
+        _ => malformed(format!("Channel brightness must be R:G:B (was '{s}')")),
+    }
+}
+
+/// Parse a comma-separated list of CRTC indices
+///
+/// Each index can be followed by `=TEMP` to give that CRTC its own
+/// temperature, in which case the temperature is returned along with it.
+fn parse_crtcs(s: &str, bounds: &TempBounds) -> Result<Vec<(usize, Option<i32>)>> {
+    let parse_index = |c: &str| {
+        c.parse::<usize>()
+            .or_else(|_| malformed(format!("CRTC must be a CRTC index (was '{c}')")))
+    };
+
+    s.split(',')
+        .map(|c| match c.split_once('=') {
+            Some((n, t)) => Ok((parse_index(n)?, Some(bounds.check(parse_temp(t)?)?))),
+            None => Ok((parse_index(c)?, None)),
+        })
+        .collect()
+}
+
+/// Parse a tint given as `R:G:B` factors between 0 and 1
+fn parse_rgb(s: &str) -> Result<[f64; 3]> {
+    let values = s
+        .split(':')
+        .map(|v| v.parse::<f64>())
+        .collect::<std::result::Result<Vec<f64>, _>>();
+
+    match values.as_deref() {
+        Ok(&[r, g, b]) if [r, g, b].iter().all(|c| (0.0..=1.0).contains(c)) => Ok([r, g, b]),
+        _ => malformed(format!(
+            "Tint must be R:G:B with each factor between 0 and 1 (was '{s}')"
+        )),
+    }
+}
+
+/// Parse a gamma value, given either as a single value applied to all
+/// channels or as a colon-separated `R:G:B` triple
+fn parse_gamma(s: &str) -> Result<[f64; 3]> {
+    let values = s
+        .split(':')
+        .map(|v| {
+            v.parse::<f64>()
+                .or_else(|_| malformed(format!("Gamma must be a number (was '{v}')")))
+        })
+        .collect::<Result<Vec<f64>>>()?;
+
+    let gamma = match values[..] {
+        [g] => [g, g, g],
+        [r, g, b] => [r, g, b],
+        _ => {
+            return malformed(format!(
+                "Gamma must be either a single value or R:G:B (was '{s}')"
+            ))
+        }
+    };
+
+    for g in gamma {
+        check_gamma(g)?;
+    }
+    Ok(gamma)
+}
+
 #[inline]
 fn malformed<T>(msg: String) -> Result<T> {
     Err(msg.into())
 }
 
+/// Why a run failed, which decides the exit status
+#[derive(Debug)]
+enum RunError {
+    /// Bad flags, environment variables or configuration file
+    Usage(Box<dyn Error>),
+
+    /// No gamma adjustment method could be used
+    NoMethod(Box<dyn Error>),
+
+    /// The display server could not be reached
+    Connection(Box<dyn Error>),
+
+    /// Anything else
+    Other(Box<dyn Error>),
+}
+
+impl RunError {
+    /// Sort out an error that happened after the arguments were parsed
+    fn classify(e: Box<dyn Error>) -> RunError {
+        match e.downcast_ref::<gamma::MethodError>() {
+            Some(gamma::MethodError::Unknown(..)) => RunError::Usage(e),
+            Some(gamma::MethodError::NoneAvailable) => RunError::NoMethod(e),
+            None if gamma::is_connection_error(&*e) => RunError::Connection(e),
+            None => RunError::Other(e),
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        match self {
+            RunError::Other(..) => 1,
+            RunError::Usage(..) => 2,
+            RunError::NoMethod(..) => 3,
+            RunError::Connection(..) => 4,
+        }
+    }
+
+    fn error(&self) -> &dyn Error {
+        match self {
+            RunError::Usage(e)
+            | RunError::NoMethod(e)
+            | RunError::Connection(e)
+            | RunError::Other(e) => e.as_ref(),
+        }
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::defaults().update_from_args()?;
+async fn main() {
+    let result = match load_args() {
+        Ok(args) => run(args).await.map_err(RunError::classify),
+        Err(e) => Err(RunError::Usage(e)),
+    };
+
+    if let Err(run_error) = result {
+        let e = run_error.error();
+        eprintln!("Error: {e}");
+        let mut source = e.source();
+        while let Some(e) = source {
+            eprintln!("  caused by: {e}");
+            source = e.source();
+        }
+        std::process::exit(run_error.exit_code());
+    }
+}
+
+/// Gather the settings from the configuration file, the environment
+/// and the command line, in that order
+fn load_args() -> Result<Args> {
+    let config = match config::default_path() {
+        Some(path) => config::load_from_path(&path)?,
+        None => config::Config::default(),
+    };
+    Args::defaults()
+        .update_from_config(&config)?
+        .update_from_env()
+        .update_from_args()
+}
+
+async fn run(mut args: Args) -> Result<()> {
+    log::set_verbose(args.verbose);
+    if let Some(ref path) = args.whitepoint_table {
+        args.gamma_options.fill.white_point_table = load_white_point_table(path);
+    }
+
     if args.help {
         usage();
         return Ok(());
     }
 
     if args.version {
-        println!("redshift-minimal-rs {VERSION}");
+        print!("{}", version_text());
+        return Ok(());
+    }
+
+    if args.list_methods {
+        println!("Available adjustment methods:");
+        for name in gamma::method_names() {
+            if name == "dummy" {
+                println!("  {name} (built-in)");
+            } else {
+                println!("  {name} (feature \"{name}\")");
+            }
+        }
+        return Ok(());
+    }
+
+    if args.self_check {
+        return match self_check() {
+            Ok(()) => {
+                println!("PASS");
+                Ok(())
+            }
+            Err(e) => {
+                println!("FAIL: {e}");
+                Err("Self-check failed".into())
+            }
+        };
+    }
+
+    args.location = location::locate(args.location_provider, args.location).await?;
+
+    let state_path = state::default_path();
+    if let Mode::Adjust(delta) = args.mode {
+        let last = match &state_path {
+            Some(path) => state::load_last_temp(path)?,
+            None => None,
+        };
+        let temp = args
+            .temp_bounds
+            .adjust(last.unwrap_or(args.temp_bounds.neutral), delta);
+        verbose!("Adjusting temperature by {delta}K to {temp}K");
+        args.mode = Mode::Manual(temp);
+    }
+
+    if args.print {
+        let setting = match args.mode {
+            Mode::Reset | Mode::Restore | Mode::Stdin => {
+                ColorSetting::neutral().with_temp(args.temp_bounds.neutral)
+            }
+            Mode::Manual(temp) | Mode::Continuous(temp) => {
+                args.color_setting(temp, SystemTime::now())?
+            }
+            Mode::Adjust(..) => unreachable!("--adjust is resolved to a temperature above"),
+        };
+        let [r, g, b] = args.gamma_options.fill.white_point_of(&setting);
+        println!("{r:.8} {g:.8} {b:.8}");
+        return Ok(());
+    }
+
+    if args.dump_ramp {
+        let gamma_state = start_method(&args).await?;
+        let ramps = gamma_state.saved_ramps();
+        if ramps.is_empty() {
+            return Err(
+                format!("Method {} cannot read back gamma ramps", gamma_state.name()).into(),
+            );
+        }
+        for output_ramps in ramps {
+            print!("{}", format_ramps(&output_ramps, args.full));
+        }
         return Ok(());
     }
 
+    if let Some(level) = args.backlight {
+        if args.gamma_options.dry_run {
+            verbose!("Would set the backlight to {level:.2}");
+        } else {
+            set_backlight(level);
+        }
+    }
+
     match args.mode {
         Mode::Reset => {
-            let mut gamma_state = gamma::init_gamma_method(args.method.as_deref())?;
-            gamma_state.start()?;
-            gamma_state.set_temperature(&ColorSetting {
-                temp: NEUTRAL_TEMP,
-                gamma: [1.0, 1.0, 1.0],
-                brightness: 1.0,
-            })?;
+            let color_setting = ColorSetting::neutral().with_temp(args.temp_bounds.neutral);
+            let mut gamma_state = start_method(&args).await?;
+            gamma_state.set_temperature(&color_setting)?;
+            if args.json {
+                println!("{}", json_summary(gamma_state.as_ref(), &color_setting));
+            }
+            if changes_ramps(&args, gamma_state.as_ref()) {
+                save_last_temp(state_path.as_deref(), args.temp_bounds.neutral);
+            }
+        }
+        Mode::Restore => {
+            let gamma_state = start_method(&args).await?;
+            gamma_state.restore()?;
         }
         Mode::Manual(temp) => {
-            let color_setting = ColorSetting {
-                temp,
-                gamma: [1.0, 1.0, 1.0],
-                brightness: 1.0,
-            };
-
-            let mut gamma_state = gamma::init_gamma_method(args.method.as_deref())?;
-            gamma_state.start()?;
+            let color_setting = args.color_setting(temp, SystemTime::now())?;
+            log_setting(&args, &color_setting);
+
+            let mut gamma_state = start_method(&args).await?;
+
+            apply_manual(gamma_state.as_mut(), &args, &color_setting).await?;
+            log_outputs(gamma_state.as_ref());
+            if args.json {
+                println!("{}", json_summary(gamma_state.as_ref(), &color_setting));
+            }
+            if changes_ramps(&args, gamma_state.as_ref()) {
+                save_last_temp(state_path.as_deref(), color_setting.temp);
+            }
+
+            if args.reset_on_exit || args.timeout.is_some() {
+                let waited = exit_signal_or_timeout(args.timeout).await;
+                restore_after(gamma_state.as_ref(), waited)?;
+            }
+        }
+        Mode::Continuous(temp) => {
+            let mut gamma_state = start_method(&args).await?;
+            run_continuous(&mut gamma_state, args, temp).await?;
+        }
+        Mode::Stdin => {
+            let mut gamma_state = start_method(&args).await?;
+            run_stdin(gamma_state.as_mut(), &args, std::io::stdin().lock())?;
+        }
+        Mode::Adjust(..) => unreachable!("--adjust is resolved to a temperature above"),
+    }
+
+    Ok(())
+}
+
+/// The output of `--version`
+///
+/// The first line is only the version, for scripts. The gamma methods
+/// compiled in follow, so that bug reports tell which features are on.
+fn version_text() -> String {
+    format!(
+        "redshift-minimal-rs {VERSION}\nmethods: {}\n",
+        gamma::method_priority().join(", ")
+    )
+}
+
+/// Use the white point table of `--whitepoint-table`
+///
+/// A table that cannot be used only gives a warning, the built-in one
+/// stays in use.
+fn load_white_point_table(path: &Path) -> Option<Arc<WhitePointTable>> {
+    let table = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()).into())
+        .and_then(|content| colorramp::parse_white_point_table(&content));
+    match table {
+        Ok(table) => {
+            verbose!("Using the white point table of {}", path.display());
+            Some(Arc::new(table))
+        }
+        Err(e) => {
+            eprintln!(
+                "WARNING: using the built-in white points, not {}: {e}",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+/// Start the gamma method selected by the arguments
+///
+/// The method that ends up being used is printed with `--verbose`, as
+/// auto-detection or `--fallback` may have picked another one.
+async fn start_method(args: &Args) -> Result<Box<dyn gamma::GammaMethod>> {
+    let gamma_state =
+        gamma::start_gamma_method(args.method.as_deref(), &args.gamma_options).await?;
+    verbose!("Using method: {}", gamma_state.name());
+    Ok(gamma_state)
+}
+
+/// Check that the neutral color setting leaves the identity ramp as is
+///
+/// The blackbody table is normalized to 6500K, so anything else means
+/// the color math is broken.
+fn self_check() -> Result<()> {
+    for size in [256, 1024, 2048] {
+        let (mut r, mut g, mut b) = (vec![0; size], vec![0; size], vec![0; size]);
+        colorramp::fill_identity(&mut r, &mut g, &mut b);
+        let identity = r.clone();
+
+        let options = FillOptions::default();
+        colorramp::fill(
+            &mut r,
+            &mut g,
+            &mut b,
+            &ColorSetting::neutral(),
+            size,
+            &options,
+        );
+
+        for (name, ramp) in [("red", r), ("green", g), ("blue", b)] {
+            if let Some(i) = (0..size).find(|&i| ramp[i] != identity[i]) {
+                return Err(format!(
+                    "{name} entry {i} of a {size} entry ramp is {} instead of {}",
+                    ramp[i], identity[i]
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply the color setting of one shot mode, or the first one of
+/// continuous mode, fading to it from the neutral temperature when the
+/// mode fades (see `Args::launch_fade`)
+async fn apply_manual(
+    gamma_state: &mut dyn gamma::GammaMethod,
+    args: &Args,
+    color_setting: &ColorSetting,
+) -> Result<()> {
+    match args.launch_fade() {
+        Some(duration) => {
+            let from = color_setting
+                .with_temp(args.temp_bounds.neutral)
+                .with_white_point(None);
+            let steps = transition::fade_steps(&from, color_setting, duration);
+            for (i, step) in steps.iter().enumerate() {
+                if i > 0 {
+                    tokio::time::sleep(transition::FADE_STEP).await;
+                }
+                gamma_state.set_temperature(step)?;
+            }
+            Ok(())
+        }
+        None => gamma_state.set_temperature(color_setting),
+    }
+}
+
+/// Run the continual mode loop
+///
+/// The color setting is faded to at launch when `--fade-on-start` was
+/// given, then recomputed and applied every `--interval` until Ctrl-C
+/// or SIGTERM is received, at which point the saved gamma ramps are
+/// restored.
+///
+/// On SIGHUP the configuration is loaded again, see `reload`. With
+/// `--reapply-on-change`, the last setting is applied again whenever the
+/// method sees that the gamma ramps changed behind its back.
+///
+/// The ramps are restored when the loop fails too, so that an error
+/// does not leave the screen tinted.
+async fn run_continuous(
+    gamma_state: &mut Box<dyn gamma::GammaMethod>,
+    args: Args,
+    temp: i32,
+) -> Result<()> {
+    let result = continuous_loop(gamma_state, args, temp).await;
+    restore_after(gamma_state.as_ref(), result)
+}
+
+/// The loop of `run_continuous`, which returns without restoring
+async fn continuous_loop(
+    gamma_state: &mut Box<dyn gamma::GammaMethod>,
+    mut args: Args,
+    mut temp: i32,
+) -> Result<()> {
+    let exit = exit_signal();
+    tokio::pin!(exit);
+    let mut hangup = HangupSignal::new()?;
+
+    // The first tick completes right away. A fade longer than the
+    // interval delays the following ticks instead of bunching them up.
+    let mut interval = tokio::time::interval(args.interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let mut hysteresis = args.min_delta.map(transition::Hysteresis::new);
+    let mut watchdog = args.reapply_interval.map(tokio::time::interval);
+    let mut last_applied = None;
+    let mut first = true;
+    loop {
+        tokio::select! {
+            res = &mut exit => {
+                res?;
+                break;
+            }
+            _ = hangup.recv() => {
+                match reload(gamma_state, &args).await {
+                    Ok((new_args, new_temp)) => {
+                        if new_args.interval != args.interval {
+                            interval = tokio::time::interval(new_args.interval);
+                            interval.set_missed_tick_behavior(
+                                tokio::time::MissedTickBehavior::Delay,
+                            );
+                            // Skip the immediate tick, the setting is applied below
+                            interval.tick().await;
+                        }
+                        hysteresis = new_args.min_delta.map(transition::Hysteresis::new);
+                        watchdog = new_args.reapply_interval.map(tokio::time::interval);
+                        (args, temp) = (new_args, new_temp);
+                    }
+                    Err(e) => {
+                        eprintln!("Error: not reloading the configuration: {e}");
+                        continue;
+                    }
+                }
+            }
+            _ = next_check(&mut watchdog) => {
+                if let Some(ref setting) = last_applied {
+                    match gamma_state.ramps_changed() {
+                        Ok(true) => {
+                            verbose!("Gamma ramps were reset, setting them again");
+                            gamma_state.set_temperature(setting)?;
+                        }
+                        Ok(false) => {}
+                        Err(e) => eprintln!("WARNING: not checking the gamma ramps: {e}"),
+                    }
+                }
+                continue;
+            }
+            _ = interval.tick() => {}
+        }
+
+        let color_setting = args.color_setting(temp, SystemTime::now())?;
+        if let Some(ref mut hysteresis) = hysteresis {
+            if !hysteresis.should_apply(&color_setting) {
+                verbose!(
+                    "Keeping {}K, {}K is within --min-delta",
+                    hysteresis.last().map_or(color_setting.temp, |s| s.temp),
+                    color_setting.temp
+                );
+                continue;
+            }
+        }
+        log_setting(&args, &color_setting);
+        if first {
+            // Fade in at launch with --fade-on-start
+            apply_manual(gamma_state.as_mut(), &args, &color_setting).await?;
+            first = false;
+        } else {
             gamma_state.set_temperature(&color_setting)?;
         }
+        last_applied = Some(color_setting);
+        if args.json {
+            println!("{}", json_summary(gamma_state.as_ref(), &color_setting));
+        }
+    }
+    Ok(())
+}
+
+/// Restore the gamma ramps, whether `result` is an error or not
+///
+/// The error of `result` is the one returned, a failure to restore
+/// after it is only a warning.
+fn restore_after<T>(gamma_state: &dyn gamma::GammaMethod, result: Result<T>) -> Result<T> {
+    match (result, gamma_state.restore()) {
+        (Ok(value), restored) => restored.map(|()| value),
+        (Err(e), Ok(())) => Err(e),
+        (Err(e), Err(restore_error)) => {
+            eprintln!("WARNING: failed to restore the gamma ramps: {restore_error}");
+            Err(e)
+        }
+    }
+}
+
+/// Wait for the next `--reapply-on-change` check, forever when there are
+/// none
+async fn next_check(watchdog: &mut Option<tokio::time::Interval>) {
+    match watchdog {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Load the configuration file, environment and arguments again
+///
+/// The started gamma method, and so the outputs it found, is kept unless
+/// `--reload-outputs` is given. In that case it is restored and replaced
+/// by a newly started one, as long as that one starts. Errors leave
+/// `current` in effect.
+async fn reload(
+    gamma_state: &mut Box<dyn gamma::GammaMethod>,
+    current: &Args,
+) -> Result<(Args, i32)> {
+    verbose!("Reloading the configuration");
+    let mut args = load_args()?;
+    // GeoClue is only asked at startup, its location is kept
+    if (args.location_provider, current.location_provider)
+        == (LocationProvider::Auto, LocationProvider::Auto)
+    {
+        args.location = current.location;
+    }
+    if let Some(ref path) = args.whitepoint_table {
+        args.gamma_options.fill.white_point_table = load_white_point_table(path);
+    }
+    let temp = match args.mode {
+        Mode::Continuous(temp) => temp,
+        mode => {
+            return Err(format!(
+                "the arguments no longer select continuous mode but {}",
+                mode.as_args()
+            )
+            .into())
+        }
+    };
+
+    if current.reload_outputs || args.reload_outputs {
+        // The ramps a new method saves have to be the original ones
+        gamma_state.restore()?;
+        match start_method(&args).await {
+            Ok(new_state) => {
+                *gamma_state = new_state;
+                log_outputs(gamma_state.as_ref());
+            }
+            Err(e) => eprintln!("Error: keeping the previous outputs: {e}"),
+        }
+    }
+    Ok((args, temp))
+}
+
+/// SIGHUP, which never arrives where there are no signals
+struct HangupSignal {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl HangupSignal {
+    fn new() -> Result<HangupSignal> {
+        Ok(HangupSignal {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.signal.recv().await;
+
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
     }
+}
 
+/// Apply the temperatures read from `input`, one per line
+///
+/// The same started method is reused for every line. Malformed lines
+/// and temperatures out of bounds only give a warning. At the end of
+/// input, or when reading or applying fails, the saved gamma ramps are
+/// restored.
+fn run_stdin(
+    gamma_state: &mut dyn gamma::GammaMethod,
+    args: &Args,
+    input: impl BufRead,
+) -> Result<()> {
+    let result = stdin_loop(gamma_state, args, input);
+    restore_after(gamma_state, result)
+}
+
+/// The loop of `run_stdin`, which returns without restoring
+fn stdin_loop(
+    gamma_state: &mut dyn gamma::GammaMethod,
+    args: &Args,
+    input: impl BufRead,
+) -> Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let temp = match parse_temp(line).and_then(|t| args.temp_bounds.check(t)) {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("WARNING: ignoring input line: {e}");
+                continue;
+            }
+        };
+
+        let color_setting = args.color_setting(temp, SystemTime::now())?;
+        log_setting(args, &color_setting);
+        gamma_state.set_temperature(&color_setting)?;
+        if args.json {
+            println!("{}", json_summary(gamma_state, &color_setting));
+        }
+    }
     Ok(())
 }
+
+/// Set the backlight for `--backlight`
+///
+/// Like the temperature, this is best effort: a missing device or a
+/// lack of permissions only gives a warning.
+#[cfg(target_os = "linux")]
+fn set_backlight(level: f64) {
+    match Backlight::find().and_then(|b| {
+        b.set_level(level)?;
+        Ok(b)
+    }) {
+        Ok(b) => verbose!("Set backlight {} to {level:.2}", b.path.display()),
+        Err(e) => eprintln!("WARNING: not setting the backlight: {e}"),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_backlight(_level: f64) {
+    eprintln!("WARNING: --backlight is only supported on Linux");
+}
+
+/// Print the color setting about to be applied under `--verbose`, and
+/// what its temperature looks like with `--describe`
+fn log_setting(args: &Args, setting: &ColorSetting) {
+    verbose!(
+        "Applying temperature {}K, brightness {:.2}, gamma {:.2}:{:.2}:{:.2}",
+        setting.temp,
+        setting.brightness,
+        setting.gamma[0],
+        setting.gamma[1],
+        setting.gamma[2]
+    );
+    if args.describe && !args.json {
+        println!("{}", transition::describe_temp(setting.temp));
+    } else {
+        verbose!("{}", transition::describe_temp(setting.temp));
+    }
+}
+
+/// Whether applying a setting actually changes the gamma ramps, and so
+/// is worth remembering for `--adjust`
+///
+/// Neither `--dry-run` nor the dummy method do.
+fn changes_ramps(args: &Args, gamma_state: &dyn gamma::GammaMethod) -> bool {
+    !args.gamma_options.dry_run && gamma_state.name() != "dummy"
+}
+
+/// Remember the temperature for later `--adjust` runs
+///
+/// The display is already adjusted at this point, so failing to save
+/// only warns instead of failing the whole run.
+fn save_last_temp(path: Option<&std::path::Path>, temp: i32) {
+    if let Some(path) = path {
+        if let Err(e) = state::save_last_temp(path, temp) {
+            eprintln!("WARNING: {e}");
+        }
+    }
+}
+
+/// Format the ramps of an output as CSV for `--dump-ramp`
+///
+/// Unless `full` is set, only the first and last `DUMP_EDGE_ENTRIES`
+/// entries are included, with a `...` line in between.
+fn format_ramps(ramps: &gamma::OutputRamps, full: bool) -> String {
+    let size = ramps.red.len().min(ramps.green.len()).min(ramps.blue.len());
+    let mut out = format!(
+        "# output {}, gamma ramp size {}\nindex,red,green,blue\n",
+        ramps.output.id, ramps.output.ramp_size
+    );
+
+    let entry = |i: usize| {
+        format!(
+            "{i},{},{},{}\n",
+            ramps.red[i], ramps.green[i], ramps.blue[i]
+        )
+    };
+    if full || size <= 2 * DUMP_EDGE_ENTRIES {
+        out.extend((0..size).map(entry));
+    } else {
+        out.extend((0..DUMP_EDGE_ENTRIES).map(entry));
+        out += "...\n";
+        out.extend((size - DUMP_EDGE_ENTRIES..size).map(entry));
+    }
+    out
+}
+
+/// Describe an applied color setting as a single line JSON object
+/// for `--json`
+///
+/// `crtcs` is the number of outputs the method adjusted.
+fn json_summary(gamma_state: &dyn gamma::GammaMethod, setting: &ColorSetting) -> String {
+    let [r, g, b] = setting.gamma;
+    format!(
+        r#"{{"method":"{}","temp":{},"brightness":{:?},"gamma":[{r:?},{g:?},{b:?}],"crtcs":{}}}"#,
+        gamma_state.name(),
+        setting.temp,
+        setting.brightness,
+        gamma_state.adjusted_outputs().len()
+    )
+}
+
+/// Print the outputs the gamma method adjusted under `--verbose`
+fn log_outputs(gamma_state: &dyn gamma::GammaMethod) {
+    for output in gamma_state.adjusted_outputs() {
+        verbose!(
+            "Adjusted output {} with gamma ramp size {}",
+            output.id,
+            output.ramp_size
+        );
+    }
+}
+
+/// Wait until the process is asked to exit, by either Ctrl-C or SIGTERM
+async fn exit_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res?,
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
+
+/// Wait for `exit_signal`, but no longer than `timeout`
+async fn exit_signal_or_timeout(timeout: Option<Duration>) -> Result<()> {
+    match timeout {
+        Some(timeout) => tokio::select! {
+            res = exit_signal() => res,
+            _ = tokio::time::sleep(timeout) => {
+                verbose!("Timeout of {:.1}s reached", timeout.as_secs_f64());
+                Ok(())
+            }
+        },
+        None => exit_signal().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redshift_minimal_rs::{MAX_BRIGHTNESS, MAX_TEMP, MIN_TEMP};
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::defaults().parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn empty_args_use_defaults() {
+        let args = parse(&[]).unwrap();
+        assert_eq!(args.mode, Mode::Manual(NEUTRAL_TEMP));
+        assert!(!args.help);
+    }
+
+    #[test]
+    fn set_rejects_non_numeric_temperature() {
+        assert!(parse(&["-S", "notanumber"]).is_err());
+    }
+
+    #[test]
+    fn oneshot_is_an_alias_for_set() {
+        assert_eq!(parse(&["-O", "3000"]).unwrap().mode, Mode::Manual(3000));
+        assert!(parse(&["-O", "3000", "-S", "4000"]).is_err());
+    }
+
+    #[test]
+    fn brightness_alone_keeps_neutral_temperature() {
+        let args = parse(&["-b", "0.5"]).unwrap();
+        assert_eq!(args.mode, Mode::Manual(NEUTRAL_TEMP));
+        assert_eq!(args.brightness, 0.5);
+    }
+
+    #[test]
+    fn brightness_can_be_given_per_channel() {
+        let args = parse(&["-S", "3000"]).unwrap();
+        let setting = args.color_setting(3000, SystemTime::now()).unwrap();
+        assert_eq!(setting.brightness_rgb, [1.0, 1.0, 1.0]);
+        assert_eq!(ColorSetting::default().brightness_rgb, [1.0, 1.0, 1.0]);
+
+        let args = parse(&["-S", "3000", "--brightness-rgb", "1:0.9:0.8"]).unwrap();
+        let setting = args.color_setting(3000, SystemTime::now()).unwrap();
+        assert_eq!(setting.brightness_rgb, [1.0, 0.9, 0.8]);
+
+        assert!(parse_brightness_rgb("1:0.9").is_err());
+        assert!(parse_brightness_rgb("1:0.9:0").is_err());
+        assert!(parse_brightness_rgb("1:x:1").is_err());
+    }
+
+    #[test]
+    fn temperatures_take_an_optional_kelvin_suffix() {
+        assert_eq!(parse_temp("3000").unwrap(), 3000);
+        assert_eq!(parse_temp("3000K").unwrap(), 3000);
+        assert_eq!(parse_temp("3000k").unwrap(), 3000);
+        assert!(parse_temp("3000C").is_err());
+        assert!(parse_temp("K").is_err());
+        assert_eq!(parse(&["-S", "3000K"]).unwrap().mode, Mode::Manual(3000));
+        assert!(parse(&["-S", "3000C"]).is_err());
+        assert!(parse(&["-S", "100K"]).is_err());
+        let args = parse(&["-t", "6000K:3500k"]).unwrap();
+        assert_eq!((args.day_temp, args.night_temp), (6000, 3500));
+    }
+
+    #[test]
+    fn presets_set_their_temperature() {
+        assert_eq!(
+            parse(&["--preset", "candle"]).unwrap().mode,
+            Mode::Manual(1900)
+        );
+        assert_eq!(
+            parse(&["--preset", "overcast", "-c"]).unwrap().mode,
+            Mode::Continuous(7000)
+        );
+        assert!(parse(&["--preset", "daylight", "-S", "3000"]).is_err());
+        match parse(&["--preset", "sunset"]) {
+            Ok(_) => panic!("unknown preset should not parse"),
+            Err(e) => assert!(e.to_string().contains("candle, incandescent, halogen")),
+        }
+    }
+
+    #[test]
+    fn mireds_are_converted_to_kelvin() {
+        assert_eq!(mired_to_kelvin(153.0), 6536);
+        assert_eq!(parse(&["--mired", "153"]).unwrap().mode, Mode::Manual(6536));
+        assert_eq!(parse(&["--mired", "250"]).unwrap().mode, Mode::Manual(4000));
+
+        // The bounds apply to the converted temperature
+        assert!(parse(&["--mired", "2000"]).is_err());
+        assert!(parse(&["--mired", "0.001"]).is_err());
+        for m in ["0", "-153", "warm", "inf"] {
+            assert!(parse_mired(m).is_err(), "{m}");
+        }
+        assert!(parse(&["--mired"]).is_err());
+        assert!(parse(&["--mired", "153", "-S", "3000"]).is_err());
+    }
+
+    #[test]
+    fn interval_is_a_positive_duration() {
+        assert_eq!(parse(&[]).unwrap().interval, UPDATE_INTERVAL);
+        let args = parse(&["-c", "--interval", "0.5"]).unwrap();
+        assert_eq!(args.interval, Duration::from_millis(500));
+        assert_eq!(parse_interval("60").unwrap(), Duration::from_secs(60));
+        for s in ["0", "-5", "soon", "inf", "NaN"] {
+            assert!(parse_interval(s).is_err(), "{s}");
+        }
+        assert!(parse(&["--interval"]).is_err());
+    }
+
+    #[test]
+    fn min_delta_is_a_non_negative_temperature() {
+        assert_eq!(parse(&[]).unwrap().min_delta, None);
+        assert_eq!(
+            parse(&["-c", "--min-delta", "50"]).unwrap().min_delta,
+            Some(50)
+        );
+        assert_eq!(parse_min_delta("100K").unwrap(), 100);
+        assert_eq!(parse_min_delta("0").unwrap(), 0);
+        for s in ["-50", "a lot", ""] {
+            assert!(parse_min_delta(s).is_err(), "{s}");
+        }
+        assert!(parse(&["--min-delta"]).is_err());
+    }
+
+    #[test]
+    fn dummy_ramp_size_is_positive() {
+        assert_eq!(parse(&[]).unwrap().gamma_options.dummy_ramp_size, None);
+        let args = parse(&["-m", "dummy", "--dummy-ramp-size", "1024"]).unwrap();
+        assert_eq!(args.gamma_options.dummy_ramp_size, Some(1024));
+        for n in ["0", "-1", "big"] {
+            assert!(parse(&["--dummy-ramp-size", n]).is_err(), "{n}");
+        }
+    }
+
+    #[test]
+    fn reapplying_is_off_by_default() {
+        assert_eq!(parse(&["-c"]).unwrap().reapply_interval, None);
+        let args = parse(&["-c", "--reapply-on-change", "2"]).unwrap();
+        assert_eq!(args.reapply_interval, Some(Duration::from_secs(2)));
+        assert!(parse(&["-c", "--reapply-on-change", "0"]).is_err());
+        assert!(parse(&["-c", "--reapply-on-change"]).is_err());
+    }
+
+    #[test]
+    fn outputs_are_kept_on_reload_by_default() {
+        assert!(!parse(&["-c"]).unwrap().reload_outputs);
+        assert!(parse(&["-c", "--reload-outputs"]).unwrap().reload_outputs);
+    }
+
+    #[test]
+    fn rgb_tint_replaces_the_temperature() {
+        let args = parse(&["--rgb", "1:0.2:0"]).unwrap();
+        assert_eq!(args.mode, Mode::Manual(NEUTRAL_TEMP));
+        let setting = args.color_setting(NEUTRAL_TEMP, SystemTime::now()).unwrap();
+        assert_eq!(setting.white_point, Some([1.0, 0.2, 0.0]));
+
+        assert!(parse(&["--rgb", "1:0.2:0", "-S", "3000"]).is_err());
+        assert!(parse(&["--preset", "candle", "--rgb", "1:0.2:0"]).is_err());
+        for s in ["1:0.2", "1:0.2:0:0", "1.5:0.2:0", "red"] {
+            assert!(parse_rgb(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn temperature_from_input() {
+        assert_eq!(read_temp(&b"3500\n"[..]).unwrap(), 3500);
+        assert_eq!(read_temp(&b" 4000K \nignored\n"[..]).unwrap(), 4000);
+        assert!(read_temp(&b""[..]).is_err());
+        assert!(read_temp(&b"warm\n"[..]).is_err());
+    }
+
+    #[tokio::test]
+    async fn manual_mode_applies_the_setting() {
+        let args = parse(&["-S", "3000", "-b", "0.8"]).unwrap();
+        let mut method = gamma::RecordingMethod::default();
+        let setting = args.color_setting(3000, SystemTime::now()).unwrap();
+        apply_manual(&mut method, &args, &setting).await.unwrap();
+
+        let expected = ColorSetting::neutral().with_temp(3000).with_brightness(0.8);
+        assert_eq!(method.settings, vec![expected]);
+    }
+
+    #[tokio::test]
+    async fn fade_steps_through_to_the_setting() {
+        let args = parse(&["-S", "3000", "--fade", "100"]).unwrap();
+        let mut method = gamma::RecordingMethod::default();
+        let setting = args.color_setting(3000, SystemTime::now()).unwrap();
+        apply_manual(&mut method, &args, &setting).await.unwrap();
+
+        assert_eq!(method.settings.len(), 4);
+        assert_eq!(method.settings.last(), Some(&setting));
+        assert!(method.settings[0].temp > 3000);
+
+        let args = parse(&["-S", "3000", "--fade-on-start", "100"]).unwrap();
+        assert_eq!(args.launch_fade(), Some(Duration::from_millis(100)));
+    }
+
+    #[tokio::test]
+    async fn continuous_mode_fades_only_on_start() {
+        let args = parse(&["-c", "-S", "3000", "--fade", "100"]).unwrap();
+        assert_eq!(args.launch_fade(), None);
+        let mut method = gamma::RecordingMethod::default();
+        let setting = args.color_setting(3000, SystemTime::now()).unwrap();
+        apply_manual(&mut method, &args, &setting).await.unwrap();
+        assert_eq!(method.settings, vec![setting]);
+
+        let args = parse(&["-c", "-S", "3000", "--fade-on-start", "100"]).unwrap();
+        let mut method = gamma::RecordingMethod::default();
+        apply_manual(&mut method, &args, &setting).await.unwrap();
+        assert_eq!(method.settings.len(), 4);
+        assert_eq!(method.settings.last(), Some(&setting));
+    }
+
+    #[test]
+    fn json_summary_of_the_setting() {
+        let args = parse(&["--json", "-S", "3000"]).unwrap();
+        assert!(args.json && args.gamma_options.quiet);
+
+        let setting = ColorSetting::neutral()
+            .with_temp(3000)
+            .with_gamma([1.0, 0.9, 0.8]);
+        assert_eq!(
+            json_summary(&gamma::RecordingMethod::default(), &setting),
+            r#"{"method":"recording","temp":3000,"brightness":1.0,"gamma":[1.0,0.9,0.8],"crtcs":0}"#
+        );
+    }
+
+    #[test]
+    fn only_applied_temperatures_are_remembered() {
+        let method = gamma::RecordingMethod::default();
+        let args = parse(&["-O", "3000"]).unwrap();
+        assert!(changes_ramps(&args, &method));
+
+        let args = parse(&["--dry-run", "-O", "3000"]).unwrap();
+        assert!(!changes_ramps(&args, &method));
+
+        let args = parse(&["-m", "dummy", "-O", "3000"]).unwrap();
+        let dummy = gamma::init_gamma_method(Some("dummy"), &args.gamma_options).unwrap();
+        assert!(!changes_ramps(&args, dummy.as_ref()));
+    }
+
+    #[test]
+    fn failures_still_restore() {
+        let method = gamma::RecordingMethod::default();
+        assert_eq!(restore_after(&method, Ok(3)).unwrap(), 3);
+        let e = restore_after::<()>(&method, Err("set failed".into())).unwrap_err();
+        assert_eq!(e.to_string(), "set failed");
+        assert_eq!(method.restores.get(), 2);
+    }
+
+    #[test]
+    fn stdin_mode_applies_every_line() {
+        let args = parse(&["--stdin", "-b", "0.8"]).unwrap();
+        assert_eq!(args.mode, Mode::Stdin);
+        assert!(parse(&["--stdin", "-S", "3000"]).is_err());
+        assert!(parse(&["--stdin", "-c"]).is_err());
+
+        let mut method = gamma::RecordingMethod::default();
+        run_stdin(&mut method, &args, &b"3000\n\nwarm\n100\n4500K\n"[..]).unwrap();
+
+        let temps = method.settings.iter().map(|s| s.temp).collect::<Vec<_>>();
+        assert_eq!(temps, vec![3000, 4500]);
+        assert!(method.settings.iter().all(|s| s.brightness == 0.8));
+        assert_eq!(method.restores.get(), 1);
+
+        // Input that cannot be read stops and restores all the same
+        let mut method = gamma::RecordingMethod::default();
+        assert!(run_stdin(&mut method, &args, &b"3000\n\xff\n4500\n"[..]).is_err());
+        assert_eq!(method.settings.len(), 1);
+        assert_eq!(method.restores.get(), 1);
+    }
+
+    #[test]
+    fn ramps_are_dumped_as_csv() {
+        let ramp = (0..10).map(|i| i * 100).collect::<Vec<u16>>();
+        let ramps = gamma::OutputRamps {
+            output: gamma::OutputInfo {
+                id: 63,
+                ramp_size: 10,
+            },
+            red: ramp.clone(),
+            green: ramp.clone(),
+            blue: ramp,
+        };
+
+        let short = format_ramps(&ramps, false);
+        let lines = short.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# output 63, gamma ramp size 10");
+        assert_eq!(lines[1], "index,red,green,blue");
+        assert_eq!(lines[2], "0,0,0,0");
+        assert_eq!(lines[6], "...");
+        assert_eq!(lines[10], "9,900,900,900");
+        assert_eq!(lines.len(), 11);
+
+        let full = format_ramps(&ramps, true);
+        assert_eq!(full.lines().count(), 12);
+        assert!(full.contains("\n5,500,500,500\n"));
+    }
+
+    #[test]
+    fn restore_is_its_own_mode() {
+        assert_eq!(parse(&["--restore"]).unwrap().mode, Mode::Restore);
+        assert_eq!(parse(&["-x"]).unwrap().mode, Mode::Reset);
+        assert!(parse(&["--restore", "-x"]).is_err());
+        assert!(parse(&["--restore", "-c"]).is_err());
+    }
+
+    #[test]
+    fn subcommands_select_the_mode() {
+        let args = parse(&["set", "--temp", "3000", "--brightness", "0.8"]).unwrap();
+        assert_eq!(args.mode, Mode::Manual(3000));
+        assert_eq!(args.brightness, 0.8);
+        assert_eq!(
+            parse(&["set", "-S", "3000"]).unwrap().mode,
+            Mode::Manual(3000)
+        );
+        assert_eq!(parse(&["reset"]).unwrap().mode, Mode::Reset);
+        assert!(parse(&["list-methods"]).unwrap().list_methods);
+        assert_eq!(
+            parse(&["daemon", "-S", "4000"]).unwrap().mode,
+            Mode::Continuous(4000)
+        );
+        assert!(parse(&["set", "-h"]).unwrap().help);
+
+        // --temp keeps its meaning outside of set
+        let args = parse(&["daemon", "-l", "51.5:-0.1", "--temp", "6000:3500"]).unwrap();
+        assert_eq!((args.day_temp, args.night_temp), (6000, 3500));
+
+        assert!(parse(&["set", "--temp"]).is_err());
+        assert!(parse(&["set", "-c"]).is_err());
+        assert!(parse(&["set", "-x"]).is_err());
+        assert!(parse(&["reset", "-S", "3000"]).is_err());
+        assert!(parse(&["daemon", "--stdin"]).is_err());
+        assert!(parse(&["-S", "3000", "set"]).is_err());
+    }
+
+    fn vars<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    #[test]
+    fn environment_variables() {
+        let args = Args::defaults().update_from_vars(vars(&[
+            ("REDSHIFT_METHOD", "dummy"),
+            ("REDSHIFT_TEMP", "3500K"),
+            ("REDSHIFT_BRIGHTNESS", "0.7"),
+        ]));
+        assert_eq!(args.method.as_deref(), Some("dummy"));
+        assert_eq!(args.mode, Mode::Manual(3500));
+        assert_eq!(args.brightness, 0.7);
+
+        let args = args.parse_args(["-S", "4000"].map(String::from)).unwrap();
+        assert_eq!(args.mode, Mode::Manual(4000));
+    }
+
+    #[test]
+    fn malformed_environment_variables_are_ignored() {
+        let args = Args::defaults().update_from_vars(vars(&[
+            ("REDSHIFT_TEMP", "100"),
+            ("REDSHIFT_BRIGHTNESS", "bright"),
+        ]));
+        assert_eq!(args.mode, Mode::Manual(NEUTRAL_TEMP));
+        assert_eq!(args.brightness, MAX_BRIGHTNESS);
+    }
+
+    #[test]
+    fn self_check_passes() {
+        assert!(parse(&["--self-check"]).unwrap().self_check);
+        self_check().unwrap();
+    }
+
+    #[test]
+    fn flags_in_any_order() {
+        let orderings: [&[&str]; 4] = [
+            &["-v", "-S", "3000", "-b", "0.8", "-m", "dummy"],
+            &["-S", "3000", "-b", "0.8", "-m", "dummy", "-v"],
+            &["-m", "dummy", "-b", "0.8", "-v", "-S", "3000"],
+            &["-b", "0.8", "-v", "-m", "dummy", "-S", "3000"],
+        ];
+        for args in orderings {
+            let args = parse(args).unwrap();
+            assert_eq!(args.mode, Mode::Manual(3000));
+            assert_eq!(args.brightness, 0.8);
+            assert_eq!(args.method.as_deref(), Some("dummy"));
+            assert!(args.verbose);
+        }
+    }
+
+    #[tokio::test]
+    async fn started_method_is_the_selected_one() {
+        let args = parse(&["-m", "dummy", "--json"]).unwrap();
+        assert_eq!(start_method(&args).await.unwrap().name(), "dummy");
+    }
+
+    #[test]
+    fn version_lists_the_methods() {
+        let text = version_text();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], format!("redshift-minimal-rs {VERSION}"));
+        assert!(lines[1].starts_with("methods: "));
+        assert!(lines[1].ends_with("dummy"));
+        #[cfg(feature = "randr")]
+        assert!(lines[1].contains("randr"));
+        #[cfg(not(feature = "drm"))]
+        assert!(!lines[1].contains("drm"));
+    }
+
+    #[test]
+    fn timeout_is_for_one_shot_mode() {
+        assert_eq!(parse(&["-S", "3000"]).unwrap().timeout, None);
+        let args = parse(&["-S", "3000", "--timeout", "10"]).unwrap();
+        assert_eq!(args.timeout, Some(Duration::from_secs(10)));
+        assert!(parse(&["--warmer", "--timeout", "10"]).is_ok());
+        assert!(parse(&["--timeout", "0"]).is_err());
+        assert!(parse(&["--timeout"]).is_err());
+        for mode in ["-x", "--restore", "-c", "--stdin"] {
+            assert!(parse(&[mode, "--timeout", "10"]).is_err(), "{mode}");
+        }
+    }
+
+    #[tokio::test]
+    async fn timeout_ends_the_wait() {
+        let timeout = Duration::from_millis(10);
+        exit_signal_or_timeout(Some(timeout)).await.unwrap();
+    }
+
+    #[test]
+    fn unknown_arguments_are_errors() {
+        match parse(&["-S", "3000", "--bogus"]) {
+            Ok(_) => panic!("--bogus should not parse"),
+            Err(e) => assert_eq!(e.to_string(), "Unknown argument '--bogus'"),
+        }
+        assert!(parse(&["3000"]).is_err());
+        assert!(parse(&["-S", "3000", "4000"]).is_err());
+    }
+
+    #[test]
+    fn unknown_arguments_get_a_suggestion() {
+        match parse(&["--temprature", "3000"]) {
+            Ok(_) => panic!("--temprature should not parse"),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Unknown argument '--temprature' (did you mean '--temp'?)"
+            ),
+        }
+        assert_eq!(suggest_flag("--temperature"), Some("--temp"));
+        assert_eq!(suggest_flag("--contineous"), Some("--continuous"));
+        assert_eq!(suggest_flag("--brightnes"), Some("--brightness"));
+        assert_eq!(suggest_flag("--gama-day"), Some("--gamma-day"));
+        assert_eq!(suggest_flag("--bogus"), None);
+        assert_eq!(suggest_flag("-temp"), None);
+
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn suggested_flags_are_known() {
+        for flag in LONG_FLAGS {
+            if let Err(e) = parse(&[flag]) {
+                assert!(!e.to_string().contains("Unknown argument"), "{flag}: {e}");
+            }
+        }
+    }
+
+    #[test]
+    fn print_flag_keeps_the_mode() {
+        let args = parse(&["-S", "3000", "--print"]).unwrap();
+        assert!(args.print);
+        assert_eq!(args.mode, Mode::Manual(3000));
+        assert!(parse(&["-p"]).unwrap().print);
+    }
+
+    #[test]
+    fn adjust_takes_a_signed_delta() {
+        assert_eq!(
+            parse(&["--adjust", "-500"]).unwrap().mode,
+            Mode::Adjust(-500)
+        );
+        assert_eq!(parse(&["--adjust", "300"]).unwrap().mode, Mode::Adjust(300));
+        assert!(parse(&["--adjust", "warmer"]).is_err());
+        assert!(parse(&["--adjust"]).is_err());
+        assert!(parse(&["--adjust", "-500", "-S", "3000"]).is_err());
+        assert!(parse(&["--adjust", "-500", "-c"]).is_err());
+    }
+
+    #[test]
+    fn warmer_and_cooler_step_the_temperature() {
+        assert_eq!(parse(&["--warmer"]).unwrap().mode, Mode::Adjust(-500));
+        assert_eq!(parse(&["--cooler"]).unwrap().mode, Mode::Adjust(500));
+        assert_eq!(
+            parse(&["--warmer", "--step", "200"]).unwrap().mode,
+            Mode::Adjust(-200)
+        );
+        assert_eq!(
+            parse(&["--step", "250K", "--cooler"]).unwrap().mode,
+            Mode::Adjust(250)
+        );
+        for s in ["0", "-100", "lots"] {
+            assert!(parse(&["--warmer", "--step", s]).is_err(), "{s}");
+        }
+        assert!(parse(&["--warmer", "--step"]).is_err());
+        assert!(parse(&["--warmer", "--cooler"]).is_err());
+        assert!(parse(&["--warmer", "--adjust", "100"]).is_err());
+    }
+
+    #[test]
+    fn adjust_is_clamped_to_the_bounds() {
+        let bounds = TempBounds::default();
+        assert_eq!(bounds.adjust(4000, -500), 3500);
+        assert_eq!(bounds.adjust(1200, -500), MIN_TEMP);
+        assert_eq!(bounds.adjust(24800, 500), MAX_TEMP);
+    }
+
+    #[test]
+    fn connect_retries() {
+        let args = parse(&[]).unwrap();
+        assert_eq!(args.gamma_options.connect_retries, gamma::CONNECT_RETRIES);
+        let args = parse(&["--connect-retries", "10"]).unwrap();
+        assert_eq!(args.gamma_options.connect_retries, 10);
+        assert!(parse(&["--connect-retries", "-1"]).is_err());
+    }
+
+    #[test]
+    fn dry_run_is_a_gamma_option() {
+        assert!(!parse(&[]).unwrap().gamma_options.dry_run);
+        assert!(
+            parse(&["--dry-run", "-S", "3000"])
+                .unwrap()
+                .gamma_options
+                .dry_run
+        );
+    }
+
+    #[test]
+    fn backlight_is_separate_from_brightness() {
+        let args = parse(&["-b", "0.5", "--backlight", "0.8"]).unwrap();
+        assert_eq!(args.brightness, 0.5);
+        assert_eq!(args.backlight, Some(0.8));
+        assert_eq!(parse(&[]).unwrap().backlight, None);
+        assert!(parse(&["--backlight", "0"]).is_err());
+        assert!(parse(&["--backlight", "bright"]).is_err());
+    }
+
+    #[test]
+    fn outputs_are_selected_by_name() {
+        let args = parse(&["--output", "eDP-1,HDMI-1", "--output", "DP-2"]).unwrap();
+        assert_eq!(args.gamma_options.outputs, vec!["eDP-1", "HDMI-1", "DP-2"]);
+        assert!(parse(&["--output"]).is_err());
+    }
+
+    #[test]
+    fn errors_have_distinct_exit_codes() {
+        let e = parse(&["--bogus"]).err().unwrap();
+        assert_eq!(RunError::Usage(e).exit_code(), 2);
+
+        let e = gamma::init_gamma_method(Some("bogus"), &gamma::GammaOptions::default())
+            .err()
+            .unwrap();
+        assert_eq!(RunError::classify(e).exit_code(), 2);
+
+        let e: Box<dyn Error> = "Self-check failed".into();
+        assert_eq!(RunError::classify(e).exit_code(), 1);
+    }
+
+    #[test]
+    fn all_screens_is_a_gamma_option() {
+        assert!(!parse(&[]).unwrap().gamma_options.all_screens);
+        let args = parse(&["--all-screens", "-S", "3000"]).unwrap();
+        assert!(args.gamma_options.all_screens);
+    }
+
+    #[test]
+    fn version_check_is_strict_by_default() {
+        assert!(!parse(&[]).unwrap().gamma_options.skip_version_check);
+        let args = parse(&["--skip-version-check"]).unwrap();
+        assert!(args.gamma_options.skip_version_check);
+    }
+
+    #[test]
+    fn verbose_flag() {
+        assert!(!parse(&["-O", "3000"]).unwrap().verbose);
+        assert!(parse(&["-v", "-O", "3000"]).unwrap().verbose);
+        assert!(parse(&["-O", "3000", "--verbose"]).unwrap().verbose);
+    }
+
+    #[test]
+    fn crtc_lists_are_merged() {
+        let args = parse(&["--crtc", "2,0", "--crtc", "1", "--crtc", "0"]).unwrap();
+        assert_eq!(args.gamma_options.crtcs, vec![0, 1, 2]);
+        assert!(parse(&["--crtc", "0,a"]).is_err());
+    }
+
+    #[test]
+    fn crtc_temperatures() {
+        let args = parse(&["--crtc", "0=3000", "--crtc", "1=5000,2", "-S", "4000"]).unwrap();
+        assert_eq!(args.gamma_options.crtcs, vec![2]);
+        assert_eq!(args.gamma_options.crtc_temps.get(&0), Some(&3000));
+        assert_eq!(args.gamma_options.crtc_temps.get(&1), Some(&5000));
+        assert_eq!(args.mode, Mode::Manual(4000));
+        assert!(parse(&["--crtc", "0=warm"]).is_err());
+        assert!(parse(&["--crtc", "0=100"]).is_err());
+        assert!(parse(&["--crtc", "a=3000"]).is_err());
+    }
+
+    #[test]
+    fn flags_override_config() {
+        let config = config::Config {
+            temp: Some(4000),
+            brightness: Some(0.5),
+            ..Default::default()
+        };
+        let args = Args::defaults()
+            .update_from_config(&config)
+            .unwrap()
+            .parse_args(["-S", "3000"].iter().map(|a| a.to_string()))
+            .unwrap();
+        assert_eq!(args.mode, Mode::Manual(3000));
+        assert_eq!(args.brightness, 0.5);
+    }
+
+    #[test]
+    fn location_follows_the_sun() {
+        let args = parse(&["-l", "51.5:-0.1"]).unwrap();
+        assert_eq!(args.location, Some((51.5, -0.1)));
+
+        // 2021-06-21, noon and midnight UTC
+        let noon = UNIX_EPOCH + Duration::from_secs(1624276800);
+        let midnight = UNIX_EPOCH + Duration::from_secs(1624233600);
+        assert_eq!(args.color_setting(3000, noon).unwrap().temp, DAY_TEMP);
+        assert_eq!(args.color_setting(3000, midnight).unwrap().temp, NIGHT_TEMP);
+    }
+
+    #[test]
+    fn location_provider_is_manual_by_default() {
+        let args = parse(&["-l", "51.5:-0.1"]).unwrap();
+        assert_eq!(args.location_provider, LocationProvider::Manual);
+
+        let args = parse(&["--location-provider", "auto", "-l", "51.5:-0.1"]).unwrap();
+        assert_eq!(args.location_provider, LocationProvider::Auto);
+        assert_eq!(args.location, Some((51.5, -0.1)));
+        assert!(parse(&["--location-provider", "auto", "-c"]).is_ok());
+
+        assert!(parse(&["--location-provider", "geoclue2"]).is_err());
+        assert!(parse(&["--location-provider"]).is_err());
+        assert!(parse(&["--location-provider", "auto", "-S", "3000"]).is_err());
+    }
+
+    #[test]
+    fn day_night_temperatures() {
+        let args = parse(&["-t", "5500:3000", "-l", "51.5:-0.1"]).unwrap();
+        assert_eq!((args.day_temp, args.night_temp), (5500, 3000));
+
+        let midnight = UNIX_EPOCH + Duration::from_secs(1624233600);
+        assert_eq!(
+            args.color_setting(NEUTRAL_TEMP, midnight).unwrap().temp,
+            3000
+        );
+
+        assert!(parse(&["-t", "3000:5500"]).is_err());
+        assert!(parse(&["-t", "5500"]).is_err());
+        assert!(parse(&["-t", "5500:100"]).is_err());
+    }
+
+    #[test]
+    fn period_overrides_the_sun() {
+        let noon = UNIX_EPOCH + Duration::from_secs(1624276800);
+        let args = parse(&["-l", "51.5:-0.1", "--period", "night"]).unwrap();
+        assert_eq!(args.color_setting(3000, noon).unwrap().temp, NIGHT_TEMP);
+
+        let args = parse(&["--period", "day", "-t", "6000:3500"]).unwrap();
+        assert_eq!(args.color_setting(3000, noon).unwrap().temp, 6000);
+
+        let args = parse(&["-l", "51.5:-0.1", "--period", "transition"]).unwrap();
+        assert_eq!(args.color_setting(3000, noon).unwrap().temp, DAY_TEMP);
+    }
+
+    #[test]
+    fn transition_curve_eases_the_alpha() {
+        assert_eq!(
+            parse(&[]).unwrap().transition_curve,
+            TransitionCurve::Linear
+        );
+        assert!(parse(&["--transition-curve", "bouncy"]).is_err());
+        assert!(parse(&["--transition-curve"]).is_err());
+
+        // 2021-06-21 20:15 UTC, the sun is setting in London
+        let dusk = UNIX_EPOCH + Duration::from_secs(1624306500);
+        let temp = |curve| {
+            parse(&["-l", "51.5:-0.1", "--transition-curve", curve])
+                .unwrap()
+                .color_setting(3000, dusk)
+                .unwrap()
+                .temp
+        };
+        let (linear, cubic) = (temp("linear"), temp("cubic"));
+        assert!(NIGHT_TEMP < linear && linear < DAY_TEMP, "{linear}");
+        assert_ne!(linear, cubic);
+        assert!(NIGHT_TEMP < temp("sine") && temp("sine") < DAY_TEMP);
+    }
+
+    #[test]
+    fn period_rejects_bad_input() {
+        assert!(parse(&["--period", "dusk"]).is_err());
+        assert!(parse(&["--period"]).is_err());
+        assert!(parse(&["--period", "night", "-S", "3000"]).is_err());
+        assert!(parse(&["--period", "transition", "-S", "3000"]).is_ok());
+    }
+
+    #[test]
+    fn day_night_brightness() {
+        let args = parse(&["-l", "51.5:-0.1", "-b", "1.0:0.6"]).unwrap();
+        assert_eq!((args.brightness, args.night_brightness), (1.0, 0.6));
+
+        let noon = UNIX_EPOCH + Duration::from_secs(1624276800);
+        let midnight = UNIX_EPOCH + Duration::from_secs(1624233600);
+        assert_eq!(args.color_setting(3000, noon).unwrap().brightness, 1.0);
+        assert_eq!(args.color_setting(3000, midnight).unwrap().brightness, 0.6);
+
+        let args = parse(&["-b", "0.8"]).unwrap();
+        assert_eq!((args.brightness, args.night_brightness), (0.8, 0.8));
+        assert!(parse(&["-b", "1.0:0.05"]).is_err());
+        assert!(parse(&["-b", "1.0:dim"]).is_err());
+        assert!(parse(&["-b", "1.0:0.5:0.5"]).is_err());
+
+        // The night brightness would never be used
+        assert!(parse(&["-b", "0.9:0.6"]).is_err());
+        assert!(parse(&["-b", "0.9:0.6", "-O", "3000"]).is_err());
+        assert!(parse(&["-b", "0.9:0.6", "--location-provider", "auto"]).is_ok());
+        assert!(parse(&["-b", "0.9:0.6", "--period", "night"]).is_ok());
+    }
+
+    #[test]
+    fn day_night_gamma() {
+        let args = parse(&[
+            "-l",
+            "51.5:-0.1",
+            "--gamma-day",
+            "1.0",
+            "--gamma-night",
+            "0.8:0.9:1.0",
+        ])
+        .unwrap();
+        assert_eq!(args.gamma, [1.0, 1.0, 1.0]);
+        assert_eq!(args.night_gamma, [0.8, 0.9, 1.0]);
+
+        let midnight = UNIX_EPOCH + Duration::from_secs(1624233600);
+        assert_eq!(
+            args.color_setting(3000, midnight).unwrap().gamma,
+            [0.8, 0.9, 1.0]
+        );
+
+        let args = parse(&["-g", "0.9"]).unwrap();
+        assert_eq!(args.gamma, [0.9, 0.9, 0.9]);
+        assert_eq!(args.night_gamma, [0.9, 0.9, 0.9]);
+        assert!(parse(&["--gamma-night", "0.0"]).is_err());
+        assert!(parse(&["--gamma-day"]).is_err());
+    }
+
+    #[test]
+    fn location_rejects_bad_input() {
+        assert!(parse(&["-l", "91:0"]).is_err());
+        assert!(parse(&["-l", "50"]).is_err());
+        assert!(parse(&["-l", "50:0", "-S", "3000"]).is_err());
+    }
+
+    #[test]
+    fn parse_gamma_single_value() {
+        assert_eq!(parse_gamma("0.8").unwrap(), [0.8, 0.8, 0.8]);
+    }
+
+    #[test]
+    fn parse_gamma_per_channel() {
+        assert_eq!(parse_gamma("0.8:0.9:1.0").unwrap(), [0.8, 0.9, 1.0]);
+    }
+
+    #[test]
+    fn parse_gamma_rejects_two_values() {
+        assert!(parse_gamma("1:2").is_err());
+    }
+
+    #[test]
+    fn parse_gamma_rejects_out_of_range() {
+        assert!(parse_gamma("0.0").is_err());
+        assert!(parse_gamma("1.0:11.0:1.0").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! Solar position computation
+//!
+//! Implements the NOAA solar position equations (based on Jean Meeus'
+//! "Astronomical Algorithms"), which are accurate to well within a
+//! degree for the years around now.
+
+use crate::Result;
+
+/// Julian day of the unix epoch
+const UNIX_EPOCH_JD: f64 = 2440587.5;
+/// Julian day of the J2000 epoch
+const J2000_JD: f64 = 2451545.0;
+
+/// Compute the elevation of the sun in degrees above the horizon
+///
+/// `unix_time` is in seconds since the unix epoch, `lat` and `lon` are in
+/// degrees with north and east being positive.
+pub fn solar_elevation(unix_time: f64, lat: f64, lon: f64) -> f64 {
+    let jd = unix_time / 86400.0 + UNIX_EPOCH_JD;
+    // Julian centuries since J2000
+    let t = (jd - J2000_JD) / 36525.0;
+
+    // Geometric mean longitude and mean anomaly of the sun
+    let l0 = (280.46646 + t * (36000.76983 + t * 0.0003032)).rem_euclid(360.0);
+    let m = 357.52911 + t * (35999.05029 - t * 0.0001537);
+    let e = 0.016708634 - t * (0.000042037 + t * 0.0000001267);
+
+    // Equation of center, giving the true and apparent longitude
+    let m_rad = m.to_radians();
+    let c = m_rad.sin() * (1.914602 - t * (0.004817 + t * 0.000014))
+        + (2.0 * m_rad).sin() * (0.019993 - t * 0.000101)
+        + (3.0 * m_rad).sin() * 0.000289;
+    let omega = (125.04 - 1934.136 * t).to_radians();
+    let lambda = (l0 + c - 0.00569 - 0.00478 * omega.sin()).to_radians();
+
+    // Obliquity of the ecliptic and the declination of the sun
+    let epsilon0 =
+        23.0 + (26.0 + (21.448 - t * (46.815 + t * (0.00059 - t * 0.001813))) / 60.0) / 60.0;
+    let epsilon = (epsilon0 + 0.00256 * omega.cos()).to_radians();
+    let declination = (epsilon.sin() * lambda.sin()).asin();
+
+    // Equation of time in minutes
+    let y = (epsilon / 2.0).tan().powi(2);
+    let l0_rad = l0.to_radians();
+    let eq_time = 4.0
+        * (y * (2.0 * l0_rad).sin() - 2.0 * e * m_rad.sin()
+            + 4.0 * e * y * m_rad.sin() * (2.0 * l0_rad).cos()
+            - 0.5 * y * y * (4.0 * l0_rad).sin()
+            - 1.25 * e * e * (2.0 * m_rad).sin())
+        .to_degrees();
+
+    // True solar time in minutes and the hour angle of the sun
+    let utc_minutes = unix_time.rem_euclid(86400.0) / 60.0;
+    let solar_time = (utc_minutes + eq_time + 4.0 * lon).rem_euclid(1440.0);
+    let hour_angle = (solar_time / 4.0 - 180.0).to_radians();
+
+    let lat = lat.to_radians();
+    let cos_zenith =
+        lat.sin() * declination.sin() + lat.cos() * declination.cos() * hour_angle.cos();
+
+    90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+/// Parse a location given as `LAT:LON`
+///
+/// Latitude must be between -90 and 90, longitude between -180 and 180,
+/// in degrees with north and east being positive.
+pub fn parse_location(s: &str) -> Result<(f64, f64)> {
+    let coords = s
+        .split(':')
+        .map(|v| v.parse::<f64>())
+        .collect::<std::result::Result<Vec<f64>, _>>();
+
+    match coords.as_deref() {
+        Ok(&[lat, lon]) if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) => {
+            Ok((lat, lon))
+        }
+        _ => Err(format!(
+            "Location must be LAT:LON with -90 <= LAT <= 90 and -180 <= LON <= 180 (was '{s}')"
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // London
+    const LAT: f64 = 51.5074;
+    const LON: f64 = -0.1278;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 0.5,
+            "elevation {actual} too far from {expected}"
+        );
+    }
+
+    #[test]
+    fn summer_solstice_noon() {
+        // 2021-06-21 12:00 UTC
+        assert_close(solar_elevation(1624276800.0, LAT, LON), 61.9);
+    }
+
+    #[test]
+    fn winter_solstice_noon() {
+        // 2021-12-21 12:00 UTC
+        assert_close(solar_elevation(1640088000.0, LAT, LON), 15.0);
+    }
+
+    #[test]
+    fn summer_solstice_midnight() {
+        // 2021-06-21 00:00 UTC
+        assert_close(solar_elevation(1624233600.0, LAT, LON), -15.0);
+    }
+
+    #[test]
+    fn parse_valid_locations() {
+        assert_eq!(parse_location("51.5074:-0.1278").unwrap(), (LAT, LON));
+        assert_eq!(parse_location("0:0").unwrap(), (0.0, 0.0));
+        assert_eq!(parse_location("-90:180").unwrap(), (-90.0, 180.0));
+        assert_eq!(parse_location("90:-180").unwrap(), (90.0, -180.0));
+    }
+
+    #[test]
+    fn out_of_range_locations_are_rejected() {
+        for s in ["90.1:0", "-91:0", "0:180.5", "0:-181", "NaN:0", "0:inf"] {
+            assert!(parse_location(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn malformed_locations_are_rejected() {
+        for s in ["50", "a:b", "", ":", "50:", "50:10:0", "50,10"] {
+            match parse_location(s) {
+                Ok(_) => panic!("'{s}' should not parse"),
+                Err(e) => assert!(e.to_string().contains("LAT:LON"), "{e}"),
+            }
+        }
+    }
+}
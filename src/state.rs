@@ -0,0 +1,72 @@
+use super::Result;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Location of the file remembering the last temperature that was set
+/// (`~/.cache/redshift-minimal-rs/last_temp` on Linux)
+pub fn default_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|d| d.join("redshift-minimal-rs").join("last_temp"))
+}
+
+/// Read the last temperature that was set from the given file
+///
+/// A missing file is not an error, it just means nothing was set yet.
+pub fn load_last_temp(path: &Path) -> Result<Option<i32>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {e}", path.display()).into()),
+    };
+
+    content
+        .trim()
+        .parse::<i32>()
+        .map(Some)
+        .map_err(|_| format!("Invalid state file {} (was '{content}')", path.display()).into())
+}
+
+/// Remember the temperature in the given file, creating its directory
+/// if needed
+pub fn save_last_temp(path: &Path, temp: i32) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    }
+    fs::write(path, format!("{temp}\n"))
+        .map_err(|e| format!("Failed to write {}: {e}", path.display()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_file_has_no_temperature() {
+        let temp = load_last_temp(Path::new("/nonexistent/last_temp")).unwrap();
+        assert_eq!(temp, None);
+    }
+
+    #[test]
+    fn saved_temperature_is_loaded() {
+        let path = std::env::temp_dir()
+            .join(format!("redshift-minimal-rs-{}", std::process::id()))
+            .join("last_temp");
+        save_last_temp(&path, 4200).unwrap();
+        assert_eq!(load_last_temp(&path).unwrap(), Some(4200));
+        save_last_temp(&path, 3700).unwrap();
+        assert_eq!(load_last_temp(&path).unwrap(), Some(3700));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn garbage_is_an_error() {
+        let path = std::env::temp_dir().join(format!(
+            "redshift-minimal-rs-garbage-{}",
+            std::process::id()
+        ));
+        fs::write(&path, "warm").unwrap();
+        assert!(load_last_temp(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,5 +1,536 @@
+use crate::colorramp::white_point_of;
+use crate::Result;
+use crate::NEUTRAL_TEMP;
+use crate::{MAX_BRIGHTNESS, MAX_GAMMA, MAX_TEMP, MIN_BRIGHTNESS, MIN_GAMMA, MIN_TEMP};
+
+use std::time::Duration;
+
+/// Time between two steps of a fade
+pub const FADE_STEP: Duration = Duration::from_millis(25);
+
+/// Solar elevation (in degrees) above which it is day
+pub const TRANSITION_HIGH: f64 = 3.0;
+/// Solar elevation (in degrees) below which it is night
+pub const TRANSITION_LOW: f64 = -6.0;
+
+/// Bounds that color temperatures are validated against
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct TempBounds {
+    pub min: i32,
+    pub max: i32,
+    pub neutral: i32,
+}
+
+impl Default for TempBounds {
+    fn default() -> TempBounds {
+        TempBounds {
+            min: MIN_TEMP,
+            max: MAX_TEMP,
+            neutral: NEUTRAL_TEMP,
+        }
+    }
+}
+
+impl TempBounds {
+    /// Shift the temperature by `delta`, staying within the bounds
+    pub fn adjust(&self, t: i32, delta: i32) -> i32 {
+        t.saturating_add(delta).clamp(self.min, self.max)
+    }
+
+    /// Make sure the temperature lies within the bounds
+    pub fn check(&self, t: i32) -> Result<i32> {
+        if !(self.min..=self.max).contains(&t) {
+            return Err(format!(
+                "Temperature must be between {} and {} (was {t})",
+                self.min, self.max
+            )
+            .into());
+        }
+        Ok(t)
+    }
+}
+
+/// Make sure the gamma of a channel lies within the supported range
+pub fn check_gamma(g: f64) -> Result<f64> {
+    if !(MIN_GAMMA..=MAX_GAMMA).contains(&g) {
+        return Err(format!("Gamma must be between {MIN_GAMMA} and {MAX_GAMMA} (was {g})").into());
+    }
+    Ok(g)
+}
+
+/// Make sure the brightness lies within the supported range
+pub fn check_brightness(b: f64) -> Result<f64> {
+    if !(MIN_BRIGHTNESS..=MAX_BRIGHTNESS).contains(&b) {
+        return Err(format!(
+            "Brightness must be between {MIN_BRIGHTNESS} and {MAX_BRIGHTNESS} (was {b})"
+        )
+        .into());
+    }
+    Ok(b)
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub struct ColorSetting {
     pub temp: i32,
     pub gamma: [f64; 3],
     pub brightness: f64,
+
+    /// Red, green and blue factors applied on top of `brightness`, for
+    /// calibrating the channels separately
+    pub brightness_rgb: [f64; 3],
+
+    /// Red, green and blue factors used instead of the blackbody white
+    /// point of `temp`, when set
+    pub white_point: Option<[f64; 3]>,
+}
+
+impl ColorSetting {
+    /// A setting with the given values, after checking that they are
+    /// within the supported ranges, the temperature within `bounds`
+    ///
+    /// The fields can still be set directly, this is for values that
+    /// come from the outside.
+    pub fn new(
+        bounds: &TempBounds,
+        temp: i32,
+        gamma: [f64; 3],
+        brightness: f64,
+    ) -> Result<ColorSetting> {
+        bounds.check(temp)?;
+        for g in gamma {
+            check_gamma(g)?;
+        }
+        check_brightness(brightness)?;
+        Ok(ColorSetting {
+            temp,
+            gamma,
+            brightness,
+            brightness_rgb: [1.0, 1.0, 1.0],
+            white_point: None,
+        })
+    }
+
+    /// The setting that leaves the display unchanged: neutral
+    /// temperature, unit gamma and full brightness
+    pub fn neutral() -> ColorSetting {
+        ColorSetting {
+            temp: NEUTRAL_TEMP,
+            gamma: [1.0, 1.0, 1.0],
+            brightness: 1.0,
+            brightness_rgb: [1.0, 1.0, 1.0],
+            white_point: None,
+        }
+    }
+
+    /// The same setting with the given temperature
+    pub fn with_temp(self, temp: i32) -> ColorSetting {
+        ColorSetting { temp, ..self }
+    }
+
+    /// The same setting with the given gamma
+    pub fn with_gamma(self, gamma: [f64; 3]) -> ColorSetting {
+        ColorSetting { gamma, ..self }
+    }
+
+    /// The same setting with the given brightness
+    pub fn with_brightness(self, brightness: f64) -> ColorSetting {
+        ColorSetting { brightness, ..self }
+    }
+
+    /// The same setting with the given per-channel brightness
+    pub fn with_brightness_rgb(self, brightness_rgb: [f64; 3]) -> ColorSetting {
+        ColorSetting {
+            brightness_rgb,
+            ..self
+        }
+    }
+
+    /// The same setting with the given white point override
+    pub fn with_white_point(self, white_point: Option<[f64; 3]>) -> ColorSetting {
+        ColorSetting {
+            white_point,
+            ..self
+        }
+    }
+}
+
+impl Default for ColorSetting {
+    fn default() -> ColorSetting {
+        ColorSetting::neutral()
+    }
+}
+
+/// Names of the temperature ranges for `describe_temp`, by the
+/// temperature each range ends below
+const TEMP_NAMES: [(i32, &str); 7] = [
+    (2000, "candlelight"),
+    (3000, "warm white (incandescent-ish)"),
+    (4000, "soft white (halogen-ish)"),
+    (5000, "neutral white"),
+    (6000, "cool white (morning sun-ish)"),
+    (7000, "daylight"),
+    (10000, "cool daylight (overcast-ish)"),
+];
+
+/// A friendly name for the look of a temperature, eg
+/// `3000K - soft white (halogen-ish)`
+pub fn describe_temp(temp: i32) -> String {
+    let name = TEMP_NAMES
+        .iter()
+        .find(|&&(below, _)| temp < below)
+        .map_or("blue sky", |&(_, name)| name);
+    format!("{temp}K - {name}")
+}
+
+/// Period of the day used to pick between the day and night settings
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Period {
+    /// Always use the day setting
+    Day,
+
+    /// Always use the night setting
+    Night,
+
+    /// Follow the position of the sun
+    Transition,
+}
+
+/// Linearly blend between the day and the night setting
+///
+/// An `alpha` of 0.0 gives the day setting, 1.0 gives the night setting.
+/// Values outside of that range are clamped. When either setting has a
+/// white point override, the white points are blended as well.
+pub fn interpolate_color_settings(
+    day: &ColorSetting,
+    night: &ColorSetting,
+    alpha: f64,
+) -> ColorSetting {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let blend = |d: f64, n: f64| (1.0 - alpha) * d + alpha * n;
+
+    ColorSetting {
+        temp: blend(day.temp as f64, night.temp as f64).round() as i32,
+        gamma: [
+            blend(day.gamma[0], night.gamma[0]),
+            blend(day.gamma[1], night.gamma[1]),
+            blend(day.gamma[2], night.gamma[2]),
+        ],
+        brightness: blend(day.brightness, night.brightness),
+        brightness_rgb: [
+            blend(day.brightness_rgb[0], night.brightness_rgb[0]),
+            blend(day.brightness_rgb[1], night.brightness_rgb[1]),
+            blend(day.brightness_rgb[2], night.brightness_rgb[2]),
+        ],
+        white_point: match (day.white_point, night.white_point) {
+            (None, None) => None,
+            _ => {
+                let (d, n) = (white_point_of(day), white_point_of(night));
+                Some([blend(d[0], n[0]), blend(d[1], n[1]), blend(d[2], n[2])])
+            }
+        },
+    }
+}
+
+/// Easing applied to the alpha of the transition between day and night
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum TransitionCurve {
+    /// Follow the elevation of the sun linearly
+    #[default]
+    Linear,
+
+    /// Change slowly at both ends and faster in the middle
+    Cubic,
+
+    /// Like cubic, following half a period of a cosine
+    Sine,
+}
+
+impl TransitionCurve {
+    /// Transform the alpha, both being between 0.0 and 1.0
+    pub fn apply(self, alpha: f64) -> f64 {
+        let alpha = alpha.clamp(0.0, 1.0);
+        match self {
+            TransitionCurve::Linear => linear(alpha),
+            TransitionCurve::Cubic => cubic(alpha),
+            TransitionCurve::Sine => sine(alpha),
+        }
+    }
+}
+
+/// The identity curve
+pub fn linear(alpha: f64) -> f64 {
+    alpha
+}
+
+/// The smoothstep curve, `3a^2 - 2a^3`
+pub fn cubic(alpha: f64) -> f64 {
+    alpha * alpha * (3.0 - 2.0 * alpha)
+}
+
+/// Half a period of a cosine, `(1 - cos(pi a)) / 2`
+pub fn sine(alpha: f64) -> f64 {
+    (1.0 - (std::f64::consts::PI * alpha).cos()) / 2.0
+}
+
+/// How far into the night the given solar elevation is
+///
+/// Gives 0.0 during the day and 1.0 during the night, with a linear
+/// transition in between. It can be passed straight to
+/// `interpolate_color_settings`.
+pub fn elevation_alpha(elevation: f64) -> f64 {
+    if elevation >= TRANSITION_HIGH {
+        0.0
+    } else if elevation <= TRANSITION_LOW {
+        1.0
+    } else {
+        (TRANSITION_HIGH - elevation) / (TRANSITION_HIGH - TRANSITION_LOW)
+    }
+}
+
+/// Settings to step through when fading from one temperature to another
+///
+/// There is one step for every `FADE_STEP` of the duration, the last one
+/// being `to` itself.
+pub fn fade_steps(from: &ColorSetting, to: &ColorSetting, duration: Duration) -> Vec<ColorSetting> {
+    let steps = (duration.as_millis() / FADE_STEP.as_millis()).max(1) as i32;
+
+    (1..=steps)
+        .map(|i| interpolate_color_settings(from, to, i as f64 / steps as f64))
+        .collect()
+}
+
+/// Keeps the applied setting from following small changes
+///
+/// A setting is only let through when its temperature differs from the
+/// last one that was by more than `min_delta`, so that an elevation
+/// hovering around a threshold does not make it flicker. Only the
+/// temperature is compared.
+#[derive(Debug, Clone)]
+pub struct Hysteresis {
+    min_delta: i32,
+    last: Option<ColorSetting>,
+}
+
+impl Hysteresis {
+    pub fn new(min_delta: i32) -> Hysteresis {
+        Hysteresis {
+            min_delta,
+            last: None,
+        }
+    }
+
+    /// The last setting that was let through
+    pub fn last(&self) -> Option<&ColorSetting> {
+        self.last.as_ref()
+    }
+
+    /// Whether `setting` should be applied, in which case it becomes
+    /// the one later settings are compared with
+    ///
+    /// The first setting is always let through.
+    pub fn should_apply(&mut self, setting: &ColorSetting) -> bool {
+        let apply = match self.last {
+            Some(last) => (setting.temp - last.temp).abs() > self.min_delta,
+            None => true,
+        };
+        if apply {
+            self.last = Some(*setting);
+        }
+        apply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting(temp: i32) -> ColorSetting {
+        ColorSetting::neutral().with_temp(temp)
+    }
+
+    #[test]
+    fn default_is_neutral() {
+        let setting = ColorSetting::default();
+        assert_eq!(setting.temp, NEUTRAL_TEMP);
+        assert_eq!(setting.gamma, [1.0, 1.0, 1.0]);
+        assert_eq!(setting.brightness, 1.0);
+        assert_eq!(setting, ColorSetting::neutral());
+    }
+
+    #[test]
+    fn new_checks_the_ranges() {
+        let bounds = TempBounds::default();
+        let checked = ColorSetting::new(&bounds, 3000, [1.0, 0.9, 0.8], 0.7).unwrap();
+        assert_eq!(
+            checked,
+            setting(3000)
+                .with_gamma([1.0, 0.9, 0.8])
+                .with_brightness(0.7)
+        );
+        assert_eq!(
+            ColorSetting::new(&bounds, NEUTRAL_TEMP, [1.0; 3], 1.0).unwrap(),
+            ColorSetting::neutral()
+        );
+
+        assert!(ColorSetting::new(&bounds, 500, [1.0; 3], 1.0).is_err());
+        assert!(ColorSetting::new(&bounds, 3000, [1.0, 0.0, 1.0], 1.0).is_err());
+        assert!(ColorSetting::new(&bounds, 3000, [1.0, f64::NAN, 1.0], 1.0).is_err());
+        assert!(ColorSetting::new(&bounds, 3000, [1.0; 3], 1.5).is_err());
+        assert!(ColorSetting::new(&bounds, 3000, [1.0; 3], 0.0).is_err());
+
+        // The temperature is checked against the given bounds
+        let narrow = TempBounds {
+            min: 2000,
+            max: 5000,
+            neutral: 4000,
+        };
+        assert!(ColorSetting::new(&narrow, 6500, [1.0; 3], 1.0).is_err());
+        let wide = TempBounds {
+            min: 500,
+            max: 40000,
+            neutral: 6500,
+        };
+        assert_eq!(
+            ColorSetting::new(&wide, 30000, [1.0; 3], 1.0).unwrap().temp,
+            30000
+        );
+    }
+
+    #[test]
+    fn temp_bounds_check() {
+        let bounds = TempBounds {
+            min: 2000,
+            max: 5000,
+            neutral: 4000,
+        };
+        assert_eq!(bounds.check(2000).unwrap(), 2000);
+        assert_eq!(bounds.check(5000).unwrap(), 5000);
+        assert!(bounds.check(1999).is_err());
+        assert!(bounds.check(5001).is_err());
+        assert_eq!(bounds.adjust(4500, 1000), 5000);
+    }
+
+    #[test]
+    fn builder_only_changes_the_given_field() {
+        let setting = ColorSetting::neutral()
+            .with_temp(3000)
+            .with_brightness(0.5)
+            .with_gamma([0.9, 1.0, 1.1]);
+        assert_eq!(setting.temp, 3000);
+        assert_eq!(setting.brightness, 0.5);
+        assert_eq!(setting.gamma, [0.9, 1.0, 1.1]);
+    }
+
+    #[test]
+    fn temperatures_are_described() {
+        assert_eq!(describe_temp(1900), "1900K - candlelight");
+        assert_eq!(describe_temp(2999), "2999K - warm white (incandescent-ish)");
+        assert_eq!(describe_temp(3000), "3000K - soft white (halogen-ish)");
+        assert_eq!(describe_temp(6500), "6500K - daylight");
+        assert_eq!(describe_temp(25000), "25000K - blue sky");
+        assert!(TEMP_NAMES.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn interpolate_midpoint() {
+        let day = setting(6500);
+        let night = setting(4500)
+            .with_gamma([0.8, 0.6, 1.0])
+            .with_brightness(0.6);
+
+        let mid = interpolate_color_settings(&day, &night, 0.5);
+        assert_eq!(mid.temp, 5500);
+        assert!((mid.brightness - 0.8).abs() < 1e-9);
+        assert!((mid.gamma[0] - 0.9).abs() < 1e-9);
+        assert!((mid.gamma[1] - 0.8).abs() < 1e-9);
+        assert!((mid.gamma[2] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn interpolate_blends_white_points() {
+        let tint = setting(6500).with_white_point(Some([1.0, 0.0, 0.0]));
+        assert_eq!(
+            interpolate_color_settings(&setting(6500), &tint, 0.5).white_point,
+            Some([1.0, 0.5, 0.5])
+        );
+        assert_eq!(
+            interpolate_color_settings(&setting(6500), &setting(3000), 0.5).white_point,
+            None
+        );
+    }
+
+    #[test]
+    fn interpolate_clamps_alpha() {
+        let (day, night) = (setting(6500), setting(4500));
+        assert_eq!(interpolate_color_settings(&day, &night, -1.0), day);
+        assert_eq!(interpolate_color_settings(&day, &night, 2.0), night);
+    }
+
+    #[test]
+    fn elevation_alpha_transition() {
+        assert_eq!(elevation_alpha(30.0), 0.0);
+        assert_eq!(elevation_alpha(-30.0), 1.0);
+        assert!((elevation_alpha(-1.5) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn transition_curves_keep_the_ends() {
+        for curve in [
+            TransitionCurve::Linear,
+            TransitionCurve::Cubic,
+            TransitionCurve::Sine,
+        ] {
+            assert!(curve.apply(0.0).abs() < 1e-12, "{curve:?}");
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-12, "{curve:?}");
+            assert!((curve.apply(0.5) - 0.5).abs() < 1e-12, "{curve:?}");
+            assert_eq!(curve.apply(-1.0), curve.apply(0.0));
+            assert_eq!(curve.apply(2.0), curve.apply(1.0));
+
+            let values = (0..=100)
+                .map(|i| curve.apply(i as f64 / 100.0))
+                .collect::<Vec<_>>();
+            assert!(values.windows(2).all(|w| w[0] <= w[1]), "{curve:?}");
+        }
+        assert_eq!(TransitionCurve::default(), TransitionCurve::Linear);
+        assert!(cubic(0.25) < linear(0.25) && sine(0.25) < linear(0.25));
+    }
+
+    #[test]
+    fn fade_steps_end_at_target() {
+        let steps = fade_steps(&setting(6500), &setting(3000), Duration::from_millis(1000));
+        assert_eq!(steps.len(), 40);
+        assert_eq!(steps.last(), Some(&setting(3000)));
+        assert!(steps.windows(2).all(|w| w[0].temp >= w[1].temp));
+    }
+
+    #[test]
+    fn short_fade_is_a_single_step() {
+        let steps = fade_steps(&setting(6500), &setting(3000), Duration::from_millis(0));
+        assert_eq!(steps, vec![setting(3000)]);
+    }
+
+    #[test]
+    fn hysteresis_ignores_oscillations() {
+        let (day, night) = (setting(6500), setting(4500));
+        let mut hysteresis = Hysteresis::new(50);
+
+        // 0.01 of the 2000K range is 20K
+        let applied = [0.5, 0.51, 0.49, 0.51, 0.49, 0.5]
+            .iter()
+            .filter(|&&alpha| {
+                hysteresis.should_apply(&interpolate_color_settings(&day, &night, alpha))
+            })
+            .count();
+        assert_eq!(applied, 1);
+        assert_eq!(hysteresis.last().map(|s| s.temp), Some(5500));
+
+        assert!(hysteresis.should_apply(&interpolate_color_settings(&day, &night, 0.6)));
+        assert_eq!(hysteresis.last().map(|s| s.temp), Some(5300));
+
+        // The delta is measured from the last applied setting, so a slow
+        // drift still gets through
+        assert!(!hysteresis.should_apply(&setting(5260)));
+        assert!(!hysteresis.should_apply(&setting(5255)));
+        assert!(hysteresis.should_apply(&setting(5240)));
+    }
 }
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Run the binary with the given arguments
+///
+/// The home directory points to an empty one, so that neither the
+/// configuration nor the last temperature of the user are involved.
+fn run(args: &[&str]) -> Output {
+    let home: PathBuf = [env!("CARGO_TARGET_TMPDIR"), "cli-home"].iter().collect();
+    std::fs::create_dir_all(&home).unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_redshift-minimal-rs"))
+        .args(args)
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_CACHE_HOME", home.join(".cache"))
+        .env_remove("REDSHIFT_METHOD")
+        .env_remove("REDSHIFT_TEMP")
+        .env_remove("REDSHIFT_BRIGHTNESS")
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn dummy_method_prints_the_setting() {
+    let output = run(&["--method", "dummy", "-S", "3000"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(stdout(&output).contains("Temperature: 3000"));
+}
+
+#[test]
+fn help_prints_the_usage() {
+    let output = run(&["--help"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("USAGE:"));
+    assert!(stdout(&output).contains("--method"));
+}
+
+#[test]
+fn version_prints_the_version() {
+    let output = run(&["--version"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with(&format!(
+        "redshift-minimal-rs {}\n",
+        env!("CARGO_PKG_VERSION")
+    )));
+}
+
+#[test]
+fn invalid_temperature_is_an_error() {
+    let output = run(&["--method", "dummy", "-S", "99999"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stdout(&output).is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Temperature must be between"));
+}